@@ -0,0 +1,98 @@
+use crate::game::EngineEvent;
+use std::collections::VecDeque;
+
+/// how long each effect last, in frames
+const CLEAR_FRAMES: u32 = 18;
+const LOCK_FRAMES: u32 = 6;
+const POPUP_FRAMES: u32 = 90;
+
+/// short effects made from the engine events, ticked once per frame
+/// NOTE: the engine dont wait for the line clear flash, the next piece fall on the
+/// collapsed grid while the cleared rows flash over it
+#[derive(Debug, Default)]
+pub struct Animations {
+    /// no flashes and no fades, popups stay readable
+    reduced_motion: bool,
+    cleared_rows: Vec<usize>,
+    cleared_left: u32,
+    locked_cells: Vec<(usize, usize)>,
+    locked_left: u32,
+    /// the first one is shown, the others wait their turn
+    popups: VecDeque<(String, u32)>,
+}
+
+impl Animations {
+    pub fn new(reduced_motion: bool) -> Self {
+        Animations {
+            reduced_motion,
+            ..Default::default()
+        }
+    }
+    pub fn push(&mut self, event: EngineEvent) {
+        match event {
+            EngineEvent::PieceLocked { cells } if !self.reduced_motion => {
+                self.locked_cells = cells;
+                self.locked_left = LOCK_FRAMES;
+                self.cleared_left = 0;
+            }
+            EngineEvent::PieceLocked { .. } => {}
+            EngineEvent::LinesCleared { rows, kind } => {
+                if !self.reduced_motion && !rows.is_empty() {
+                    self.cleared_rows = rows;
+                    self.cleared_left = CLEAR_FRAMES;
+                    // its cells moved with the collapse, the rows flash instead
+                    self.locked_left = 0;
+                }
+                // a single is not worth a popup
+                if kind.lines > 1 || kind.t_spin {
+                    // NOTE: the older clears are not worth waiting for
+                    self.popups.clear();
+                    self.popups.push_back((kind.name(), POPUP_FRAMES));
+                }
+            }
+            // after the clear of the same frame, if there is one
+            EngineEvent::LevelUp(level) => self
+                .popups
+                .push_back((format!("LEVEL {}", level), POPUP_FRAMES)),
+            EngineEvent::Rewound => self.reset(),
+        }
+    }
+    /// drop the effects of a game that is not on screen anymore
+    pub fn reset(&mut self) {
+        *self = Animations::new(self.reduced_motion);
+    }
+    pub fn tick(&mut self) {
+        self.cleared_left = self.cleared_left.saturating_sub(1);
+        self.locked_left = self.locked_left.saturating_sub(1);
+        if let Some((_, left)) = self.popups.front_mut() {
+            *left -= 1;
+            if *left == 0 {
+                self.popups.pop_front();
+            }
+        }
+    }
+    /// the cleared rows, blinking, where they were before the collapse
+    /// NOTE: drawn over the collapsed grid, under the falling piece
+    pub fn highlights(&self) -> &[usize] {
+        if self.cleared_left > 0 && (self.cleared_left / 3).is_multiple_of(2) {
+            &self.cleared_rows
+        } else {
+            &[]
+        }
+    }
+    /// the cells of the piece that just locked
+    pub fn flash(&self) -> &[(usize, usize)] {
+        if self.locked_left > 0 {
+            &self.locked_cells
+        } else {
+            &[]
+        }
+    }
+    /// the popup text, and if it is fading out
+    pub fn popup(&self) -> Option<(&str, bool)> {
+        self.popups.front().map(|(text, left)| {
+            let fading = !self.reduced_motion && *left < POPUP_FRAMES / 3;
+            (text.as_str(), fading)
+        })
+    }
+}
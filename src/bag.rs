@@ -1,85 +1,96 @@
-use crate::tetromino::Tetromino;
+use crate::rng::Rng;
+use crate::tetromino::{PieceKind, Tetromino};
 use crate::vec2::Vec2;
 use rand::prelude::SliceRandom;
-use rand::rng;
-use ratatui::style::Color::*;
 
+/// the current tetromino is the last one, the next ones come before it
 pub type Bag = Vec<Tetromino>;
+/// the bag always hold at least this many tetrominos after the current one
+pub const PREVIEW_MAX: usize = 6;
 
-pub fn new_bag() -> Bag {
+/// shuffle a new bag in front of the queue when it run low
+pub fn refill(bag: &mut Bag, rng: &mut Rng) {
+    while bag.len() <= PREVIEW_MAX {
+        let mut next_bag = new_bag(rng);
+        next_bag.append(bag);
+        *bag = next_bag;
+    }
+}
+
+pub fn new_bag(rng: &mut Rng) -> Bag {
     let mut bag: Vec<Tetromino> = vec![
         Tetromino {
+            kind: PieceKind::I,
             blocks: vec![
                 Vec2 { x: -1, y: 0 },
                 Vec2 { x: 0, y: 0 },
                 Vec2 { x: 1, y: 0 },
                 Vec2 { x: 2, y: 0 },
             ],
-            color: Red,
             ..Default::default()
         },
         Tetromino {
+            kind: PieceKind::O,
             blocks: vec![
                 Vec2 { x: 0, y: 0 },
                 Vec2 { x: 1, y: 0 },
                 Vec2 { x: 0, y: 1 },
                 Vec2 { x: 1, y: 1 },
             ],
-            color: Blue,
             does_rotate: false,
             ..Default::default()
         },
         Tetromino {
+            kind: PieceKind::Z,
             blocks: vec![
                 Vec2::new(0, 0),
                 Vec2::new(-1, 0),
                 Vec2::new(0, 1),
                 Vec2::new(1, 1),
             ],
-            color: Green,
             ..Default::default()
         },
         Tetromino {
+            kind: PieceKind::S,
             blocks: vec![
                 Vec2::new(0, 0),
                 Vec2::new(1, 0),
                 Vec2::new(0, 1),
                 Vec2::new(-1, 1),
             ],
-            color: Cyan,
             ..Default::default()
         },
         Tetromino {
+            kind: PieceKind::T,
             blocks: vec![
                 Vec2::new(0, 0),
                 Vec2::new(1, 0),
                 Vec2::new(-1, 0),
                 Vec2::new(0, 1),
             ],
-            color: Yellow,
             ..Default::default()
         },
         Tetromino {
+            kind: PieceKind::L,
             blocks: vec![
                 Vec2::new(-1, 1),
                 Vec2::new(-1, 0),
                 Vec2::new(0, 0),
                 Vec2::new(1, 0),
             ],
-            color: Magenta,
             ..Default::default()
         },
         Tetromino {
+            kind: PieceKind::J,
             blocks: vec![
                 Vec2::new(1, 1),
                 Vec2::new(1, 0),
                 Vec2::new(0, 0),
                 Vec2::new(-1, 0),
             ],
-            color: White,
             ..Default::default()
         },
     ];
-    bag.shuffle(&mut rng());
+    bag.shuffle(rng);
     bag
 }
@@ -0,0 +1,241 @@
+use ratatui::{buffer::Buffer, layout::Rect, style::Color, style::Style, widgets::Widget};
+
+use crate::grid::*;
+use crate::tetromino::{PieceKind, Tetromino};
+use crate::theme::Theme;
+
+/// how many board cells go in a terminal cell, the biggest that fit is picked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoardMode {
+    /// cells of `n` rows by `2n` columns, with the theme glyphs
+    Blocks(u16),
+    /// two rows per terminal row with "▀" and "▄"
+    HalfBlock,
+    /// 2x4 cells per braille character, one color per character
+    Braille,
+}
+
+impl BoardMode {
+    /// None when even braille dont fit
+    pub fn fit(width: u16, height: u16) -> Option<Self> {
+        let cell_height = (height / GRID_HEIGHT as u16).min(width / (GRID_WIDTH as u16 * 2));
+        [
+            BoardMode::Blocks(cell_height),
+            BoardMode::HalfBlock,
+            BoardMode::Braille,
+        ]
+        .into_iter()
+        .find(|mode| {
+            let (w, h) = mode.size();
+            w > 0 && w <= width && h <= height
+        })
+    }
+    /// in terminal cells, without borders
+    pub fn size(&self) -> (u16, u16) {
+        let (width, height) = (GRID_WIDTH as u16, GRID_HEIGHT as u16);
+        match self {
+            BoardMode::Blocks(cell_height) => (width * cell_height * 2, height * cell_height),
+            BoardMode::HalfBlock => (width, height.div_ceil(2)),
+            BoardMode::Braille => (width.div_ceil(2), height.div_ceil(4)),
+        }
+    }
+}
+
+/// what end up in a cell once everything is on top of the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+    Empty,
+    Piece(PieceKind),
+    Ghost(PieceKind),
+    Garbage,
+    /// whole row drawn in the highlight color (line clears...)
+    Highlight,
+}
+
+/// the playfield, no borders, drawn straight into the buffer
+/// NOTE: it pick the biggest `BoardMode` that fit the area, see `BoardMode::fit`
+pub struct Board<'a> {
+    grid: &'a Grid,
+    theme: &'a Theme,
+    active: Option<&'a Tetromino>,
+    ghost: bool,
+    /// rows from the bottom drawn as garbage
+    garbage: usize,
+    highlights: &'a [usize],
+    /// single cells drawn like the highlights, as (x, y)
+    flash: &'a [(usize, usize)],
+}
+
+impl<'a> Board<'a> {
+    pub fn new(grid: &'a Grid, theme: &'a Theme) -> Self {
+        Board {
+            grid,
+            theme,
+            active: None,
+            ghost: false,
+            garbage: 0,
+            highlights: &[],
+            flash: &[],
+        }
+    }
+    /// the falling tetromino, not stamped on the grid yet
+    pub fn active(mut self, tetromino: &'a Tetromino) -> Self {
+        self.active = Some(tetromino);
+        self
+    }
+    /// show where the active tetromino would land
+    pub fn ghost(mut self, ghost: bool) -> Self {
+        self.ghost = ghost;
+        self
+    }
+    /// nothing send garbage yet, for versus and spectating
+    pub fn garbage(mut self, rows: usize) -> Self {
+        self.garbage = rows;
+        self
+    }
+    pub fn highlights(mut self, rows: &'a [usize]) -> Self {
+        self.highlights = rows;
+        self
+    }
+    pub fn flash(mut self, cells: &'a [(usize, usize)]) -> Self {
+        self.flash = cells;
+        self
+    }
+    fn looks(&self) -> [[Look; GRID_WIDTH]; GRID_HEIGHT] {
+        let mut looks = [[Look::Empty; GRID_WIDTH]; GRID_HEIGHT];
+        for (y, line) in self.grid.iter().enumerate() {
+            let garbage = y >= GRID_HEIGHT.saturating_sub(self.garbage);
+            for (x, cell) in line.iter().enumerate() {
+                looks[y][x] = match cell {
+                    Some(_) if garbage => Look::Garbage,
+                    Some(kind) => Look::Piece(*kind),
+                    None => Look::Empty,
+                };
+            }
+        }
+        // under the falling piece, the grid under them already moved on
+        for y in self.highlights.iter().filter(|y| **y < GRID_HEIGHT) {
+            looks[*y] = [Look::Highlight; GRID_WIDTH];
+        }
+        for (x, y) in self.flash.iter() {
+            if *x < GRID_WIDTH && *y < GRID_HEIGHT {
+                looks[*y][*x] = Look::Highlight;
+            }
+        }
+        let mut put = |tetromino: &Tetromino, look: Look| {
+            for block in tetromino.blocks.iter() {
+                let (x, y) = (tetromino.pos.x + block.x, tetromino.pos.y + block.y);
+                if x >= 0 && y >= 0 && (x as usize) < GRID_WIDTH && (y as usize) < GRID_HEIGHT {
+                    looks[y as usize][x as usize] = look;
+                }
+            }
+        };
+        if let Some(active) = self.active {
+            if self.ghost {
+                let mut ghost = active.clone();
+                while ghost.try_move_down(self.grid) {}
+                put(&ghost, Look::Ghost(ghost.kind));
+            }
+            put(active, Look::Piece(active.kind));
+        }
+        looks
+    }
+    fn color(&self, look: Look) -> Option<Color> {
+        let theme = self.theme;
+        match look {
+            Look::Empty => None,
+            Look::Piece(kind) => Some(theme.color(kind)),
+            Look::Ghost(kind) => Some(theme.ghost.color.unwrap_or(theme.color(kind))),
+            Look::Garbage => Some(theme.garbage_color),
+            Look::Highlight => Some(theme.highlight_color),
+        }
+    }
+}
+
+impl Widget for Board<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = area.intersection(buf.area);
+        let Some(mode) = BoardMode::fit(area.width, area.height) else {
+            return;
+        };
+        let looks = self.looks();
+        let theme = self.theme;
+        match mode {
+            BoardMode::Blocks(cell_height) => {
+                let cell_width = cell_height * 2;
+                for (i, line) in looks.iter().enumerate() {
+                    for (j, look) in line.iter().enumerate() {
+                        let cell_rect = Rect {
+                            x: area.x + (j as u16) * cell_width,
+                            y: area.y + (i as u16) * cell_height,
+                            width: cell_width,
+                            height: cell_height,
+                        };
+                        for (n, row) in cell_rect.rows().enumerate() {
+                            let (text, style) = match look {
+                                Look::Piece(kind) => theme.cell(*kind, row.width, n == 0),
+                                Look::Ghost(_) => (
+                                    Theme::fill(&theme.ghost.glyph, row.width),
+                                    theme.style(self.color(*look).unwrap_or_default()),
+                                ),
+                                Look::Garbage | Look::Highlight => (
+                                    Theme::fill(&theme.cell, row.width),
+                                    theme.style(self.color(*look).unwrap_or_default()),
+                                ),
+                                Look::Empty if n == 0 => (theme.empty.clone(), Style::default()),
+                                Look::Empty => continue,
+                            };
+                            buf.set_stringn(row.x, row.y, &text, row.width as usize, style);
+                        }
+                    }
+                }
+            }
+            BoardMode::HalfBlock => {
+                for (row, y) in area.rows().zip((0..GRID_HEIGHT).step_by(2)) {
+                    for (x, column) in (row.x..row.right()).zip(0..GRID_WIDTH) {
+                        let top = self.color(looks[y][column]);
+                        let bottom = looks.get(y + 1).and_then(|line| self.color(line[column]));
+                        let (symbol, style) = match (top, bottom) {
+                            (Some(top), Some(bottom)) if top == bottom => ("█", theme.style(top)),
+                            (Some(top), Some(bottom)) => ("▀", theme.style(top).bg(bottom)),
+                            (Some(top), None) => ("▀", theme.style(top)),
+                            (None, Some(bottom)) => ("▄", theme.style(bottom)),
+                            (None, None) => (" ", Style::default()),
+                        };
+                        buf[(x, row.y)].set_symbol(symbol).set_style(style);
+                    }
+                }
+            }
+            BoardMode::Braille => {
+                // bit of each dot, by [y][x] inside the character
+                const DOTS: [[u32; 2]; 4] =
+                    [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+                for (row, y) in area.rows().zip((0..GRID_HEIGHT).step_by(4)) {
+                    for (x, column) in (row.x..row.right()).zip((0..GRID_WIDTH).step_by(2)) {
+                        let mut bits = 0;
+                        let mut color = None;
+                        for (dy, dots) in DOTS.iter().enumerate() {
+                            for (dx, dot) in dots.iter().enumerate() {
+                                let Some(look) =
+                                    looks.get(y + dy).and_then(|line| line.get(column + dx))
+                                else {
+                                    continue;
+                                };
+                                if let Some(cell) = self.color(*look) {
+                                    bits |= dot;
+                                    // pieces win over the ghost
+                                    if color.is_none() || !matches!(look, Look::Ghost(_)) {
+                                        color = Some(cell);
+                                    }
+                                }
+                            }
+                        }
+                        let symbol = char::from_u32(0x2800 + bits).expect("braille range");
+                        let style = color.map(|color| theme.style(color)).unwrap_or_default();
+                        buf[(x, row.y)].set_char(symbol).set_style(style);
+                    }
+                }
+            }
+        }
+    }
+}
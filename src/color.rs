@@ -0,0 +1,173 @@
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+/// what the terminal can show, colors get mapped down to it
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSupport {
+    None,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+/// xterm defaults, in the order of the ansi codes
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+/// the 6 levels of the 256 colors cube
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorSupport {
+    /// NO_COLOR (https://no-color.org), then COLORTERM, then TERM
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        if !var("NO_COLOR").is_empty() {
+            return ColorSupport::None;
+        }
+        if matches!(var("COLORTERM").as_str(), "truecolor" | "24bit") {
+            return ColorSupport::TrueColor;
+        }
+        match var("TERM").as_str() {
+            "" | "dumb" => ColorSupport::None,
+            term if term.contains("256color") => ColorSupport::Ansi256,
+            _ => ColorSupport::Ansi16,
+        }
+    }
+    /// the closest color the terminal can show
+    pub fn downmap(&self, color: Color) -> Color {
+        match self {
+            ColorSupport::TrueColor => color,
+            ColorSupport::None => Color::Reset,
+            ColorSupport::Ansi256 => match color {
+                Color::Rgb(r, g, b) => Color::Indexed(nearest_256((r, g, b))),
+                _ => color,
+            },
+            ColorSupport::Ansi16 => match rgb(color) {
+                Some(target) => nearest_16(target),
+                None => color,
+            },
+        }
+    }
+}
+
+/// None for the colors that already are one of the 16 (or reset)
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        Color::Indexed(i) => Some(indexed_rgb(i)),
+        _ => None,
+    }
+}
+
+fn indexed_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..16 => ANSI16[i as usize].1,
+        16..232 => {
+            let i = i - 16;
+            (
+                CUBE[i as usize / 36],
+                CUBE[i as usize / 6 % 6],
+                CUBE[i as usize % 6],
+            )
+        }
+        _ => {
+            let level = 8 + (i - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// weighted for the eye, green matter the most
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    2 * d(a.0, b.0) + 4 * d(a.1, b.1) + 3 * d(a.2, b.2)
+}
+
+fn nearest_16(target: (u8, u8, u8)) -> Color {
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, target))
+        .map(|(color, _)| *color)
+        .expect("ANSI16 not empty")
+}
+
+/// the closest of the cube and the gray ramp, the 16 first depend on the terminal
+fn nearest_256(target: (u8, u8, u8)) -> u8 {
+    let level = |x: u8| {
+        (0..CUBE.len())
+            .min_by_key(|i| (CUBE[*i] as i32 - x as i32).abs())
+            .expect("CUBE not empty")
+    };
+    let cube = 16 + 36 * level(target.0) + 6 * level(target.1) + level(target.2);
+    let average = (target.0 as u32 + target.1 as u32 + target.2 as u32) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23) as usize;
+    [cube as u8, gray as u8]
+        .into_iter()
+        .min_by_key(|i| distance(indexed_rgb(*i), target))
+        .expect("two candidates")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORANGE: Color = Color::Rgb(255, 165, 0);
+
+    #[test]
+    fn rgb_on_256_colors() {
+        let support = ColorSupport::Ansi256;
+        assert_eq!(support.downmap(Color::Rgb(255, 0, 0)), Color::Indexed(196));
+        assert_eq!(support.downmap(ORANGE), Color::Indexed(214));
+        // a gray is closer on the ramp than in the cube
+        assert_eq!(
+            support.downmap(Color::Rgb(128, 128, 128)),
+            Color::Indexed(244)
+        );
+        assert_eq!(support.downmap(Color::Rgb(0, 0, 0)), Color::Indexed(16));
+        // the 16 are left to the terminal
+        assert_eq!(support.downmap(Color::Cyan), Color::Cyan);
+        assert_eq!(support.downmap(Color::Indexed(42)), Color::Indexed(42));
+    }
+
+    #[test]
+    fn nearest_256_give_back_the_exact_colors() {
+        for i in 16..=255 {
+            assert_eq!(nearest_256(indexed_rgb(i)), i);
+        }
+    }
+
+    #[test]
+    fn rgb_on_16_colors() {
+        let support = ColorSupport::Ansi16;
+        assert_eq!(support.downmap(Color::Rgb(250, 5, 5)), Color::LightRed);
+        assert_eq!(support.downmap(Color::Rgb(200, 0, 0)), Color::Red);
+        assert_eq!(support.downmap(ORANGE), Color::Yellow);
+        assert_eq!(support.downmap(Color::Indexed(196)), Color::LightRed);
+        assert_eq!(support.downmap(Color::Blue), Color::Blue);
+        assert_eq!(support.downmap(Color::Reset), Color::Reset);
+    }
+
+    #[test]
+    fn no_color_at_all() {
+        for color in [ORANGE, Color::Indexed(196), Color::Blue, Color::Reset] {
+            assert_eq!(ColorSupport::None.downmap(color), Color::Reset);
+        }
+        assert_eq!(ColorSupport::TrueColor.downmap(ORANGE), ORANGE);
+    }
+}
@@ -0,0 +1,536 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::bag::PREVIEW_MAX;
+use crate::color::ColorSupport;
+use crate::theme::{CellLabels, Palette};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Invalid(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+/// a key with its modifiers, written like "h", "Space" or "ctrl+r" in the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeySpec {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeySpec {
+    pub const fn new(code: KeyCode) -> Self {
+        KeySpec {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+    pub fn from_event(key: KeyEvent) -> Self {
+        KeySpec {
+            code: key.code,
+            // NOTE: shift is already in the char ('H' vs 'h')
+            modifiers: key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT),
+        }
+    }
+    pub fn matches(&self, key: KeyEvent) -> bool {
+        *self == KeySpec::from_event(key)
+    }
+}
+
+impl TryFrom<String> for KeySpec {
+    type Error = String;
+    fn try_from(string: String) -> Result<Self, Self::Error> {
+        let mut parts: Vec<&str> = string.split('+').collect();
+        // "+" alone, or "ctrl++"
+        if string.ends_with("++") || string == "+" {
+            parts.pop();
+            *parts.last_mut().expect("split never empty") = "+";
+        }
+        let key = parts.pop().expect("split never empty");
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                _ => {
+                    return Err(format!(
+                        "unknown modifier \"{}\" in \"{}\"",
+                        modifier, string
+                    ));
+                }
+            };
+        }
+        let code = match key.to_lowercase().as_str() {
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "space" => KeyCode::Char(' '),
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            _ if key.chars().count() == 1 => KeyCode::Char(key.chars().next().expect("one char")),
+            _ => match key
+                .strip_prefix(['f', 'F'])
+                .and_then(|n| n.parse::<u8>().ok())
+            {
+                Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                _ => return Err(format!("unknown key \"{}\"", string)),
+            },
+        };
+        Ok(KeySpec { code, modifiers })
+    }
+}
+
+impl Display for KeySpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        match self.code {
+            KeyCode::Left => write!(f, "Left"),
+            KeyCode::Right => write!(f, "Right"),
+            KeyCode::Up => write!(f, "Up"),
+            KeyCode::Down => write!(f, "Down"),
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::Backspace => write!(f, "Backspace"),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+impl Serialize for KeySpec {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for KeySpec {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        KeySpec::try_from(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Left,
+    Right,
+    Rotate,
+    SoftDrop,
+    HardDrop,
+    Undo,
+    Redo,
+    Pause,
+    Quit,
+}
+
+impl Binding {
+    pub const ALL: [Binding; 9] = [
+        Binding::Left,
+        Binding::Right,
+        Binding::Rotate,
+        Binding::SoftDrop,
+        Binding::HardDrop,
+        Binding::Undo,
+        Binding::Redo,
+        Binding::Pause,
+        Binding::Quit,
+    ];
+    /// same as in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Binding::Left => "left",
+            Binding::Right => "right",
+            Binding::Rotate => "rotate",
+            Binding::SoftDrop => "soft_drop",
+            Binding::HardDrop => "hard_drop",
+            Binding::Undo => "undo",
+            Binding::Redo => "redo",
+            Binding::Pause => "pause",
+            Binding::Quit => "quit",
+        }
+    }
+    /// cant play without those
+    fn required(&self) -> bool {
+        !matches!(self, Binding::SoftDrop | Binding::Undo | Binding::Redo)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub left: Vec<KeySpec>,
+    pub right: Vec<KeySpec>,
+    pub rotate: Vec<KeySpec>,
+    pub soft_drop: Vec<KeySpec>,
+    pub hard_drop: Vec<KeySpec>,
+    pub undo: Vec<KeySpec>,
+    pub redo: Vec<KeySpec>,
+    pub pause: Vec<KeySpec>,
+    pub quit: Vec<KeySpec>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            left: vec![
+                KeySpec::new(KeyCode::Left),
+                KeySpec::new(KeyCode::Char('h')),
+            ],
+            right: vec![
+                KeySpec::new(KeyCode::Right),
+                KeySpec::new(KeyCode::Char('l')),
+            ],
+            rotate: vec![KeySpec::new(KeyCode::Up), KeySpec::new(KeyCode::Char('k'))],
+            soft_drop: vec![KeySpec::new(KeyCode::Down)],
+            hard_drop: vec![
+                KeySpec::new(KeyCode::Char(' ')),
+                KeySpec::new(KeyCode::Char('j')),
+            ],
+            undo: vec![KeySpec::new(KeyCode::Char('u'))],
+            redo: vec![KeySpec {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL,
+            }],
+            pause: vec![KeySpec::new(KeyCode::Char('p'))],
+            quit: vec![KeySpec::new(KeyCode::Esc)],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn get(&self, binding: Binding) -> &Vec<KeySpec> {
+        match binding {
+            Binding::Left => &self.left,
+            Binding::Right => &self.right,
+            Binding::Rotate => &self.rotate,
+            Binding::SoftDrop => &self.soft_drop,
+            Binding::HardDrop => &self.hard_drop,
+            Binding::Undo => &self.undo,
+            Binding::Redo => &self.redo,
+            Binding::Pause => &self.pause,
+            Binding::Quit => &self.quit,
+        }
+    }
+    pub fn get_mut(&mut self, binding: Binding) -> &mut Vec<KeySpec> {
+        match binding {
+            Binding::Left => &mut self.left,
+            Binding::Right => &mut self.right,
+            Binding::Rotate => &mut self.rotate,
+            Binding::SoftDrop => &mut self.soft_drop,
+            Binding::HardDrop => &mut self.hard_drop,
+            Binding::Undo => &mut self.undo,
+            Binding::Redo => &mut self.redo,
+            Binding::Pause => &mut self.pause,
+            Binding::Quit => &mut self.quit,
+        }
+    }
+    pub fn binding_for(&self, key: KeyEvent) -> Option<Binding> {
+        Binding::ALL
+            .into_iter()
+            .find(|binding| self.get(*binding).iter().any(|spec| spec.matches(key)))
+    }
+    /// the other binding already using this key, if any
+    pub fn conflict(&self, binding: Binding, key: KeySpec) -> Option<Binding> {
+        Binding::ALL
+            .into_iter()
+            .find(|other| *other != binding && self.get(*other).contains(&key))
+    }
+}
+
+/// key repeat, done by the game when the terminal report key releases
+/// NOTE: only kitty style terminals do, the others use their own key repeat
+/// and these are ignored
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handling {
+    /// delayed auto shift, ms before a held left / right start repeating
+    pub das: u64,
+    /// auto repeat rate, ms between repeated moves
+    pub arr: u64,
+    /// soft drop factor, how many times faster than gravity
+    pub sdf: u64,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: 170,
+            arr: 50,
+            sdf: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub ghost: bool,
+    /// number of next tetrominos shown
+    pub preview: usize,
+    /// a preset, or the name of a file in the themes dir next to the config
+    pub theme: String,
+    pub palette: Palette,
+    pub high_contrast: bool,
+    pub labels: CellLabels,
+    /// None to detect it
+    pub colors: Option<ColorSupport>,
+    /// no flashes or fades
+    pub reduced_motion: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            ghost: true,
+            preview: 3,
+            theme: "default".to_owned(),
+            palette: Palette::Theme,
+            high_contrast: false,
+            labels: CellLabels::None,
+            colors: None,
+            reduced_motion: false,
+        }
+    }
+}
+
+/// how the leaderboard is stored, see `storage`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// one json file, rewritten at each save
+    #[default]
+    Json,
+    /// needs the `sqlite` feature
+    Sqlite,
+}
+
+impl Backend {
+    /// in the data dir or the shared dir, when there is no path in the config
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Backend::Json => "scores",
+            Backend::Sqlite => "scores.db",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaderboardConfig {
+    /// None for the default, see `leaderboard::SHARED_DIR`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub backend: Backend,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub keys: KeyBindings,
+    pub handling: Handling,
+    pub display: DisplayConfig,
+    pub leaderboard: LeaderboardConfig,
+}
+
+/// $XDG_CONFIG_HOME/tetris/config.toml, or ~/.config/tetris/config.toml
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("tetris").join("config.toml"))
+}
+
+/// $XDG_DATA_HOME/tetris, or ~/.local/share/tetris
+pub fn data_dir() -> Option<PathBuf> {
+    let data_home = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_home.join("tetris"))
+}
+
+impl Config {
+    /// an explicit path must exist, the default one is optional
+    pub fn load(path: &Path, explicit: bool) -> Result<Self, ConfigError> {
+        let string = match std::fs::read_to_string(path) {
+            Ok(string) => string,
+            Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default());
+            }
+            Err(e) => return Err(ConfigError::Io(path.to_owned(), e)),
+        };
+        let config: Config =
+            toml::from_str(&string).map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        config
+            .validate()
+            .map_err(|message| ConfigError::Invalid(path.to_owned(), message))?;
+        Ok(config)
+    }
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let string = toml::to_string_pretty(self)
+            .map_err(|e| ConfigError::Invalid(path.to_owned(), e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        }
+        // NOTE: a crash mid write would leave a half config that fail to load
+        crate::leaderboard::write_atomic(path, &string)
+            .map_err(|e| ConfigError::Io(path.to_owned(), e))
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        for binding in Binding::ALL {
+            let keys = self.keys.get(binding);
+            if binding.required() && keys.is_empty() {
+                return Err(format!("keys.{}: no key bound", binding.name()));
+            }
+            for key in keys {
+                if let Some(other) = self.keys.conflict(binding, *key) {
+                    return Err(format!(
+                        "keys.{}: \"{}\" is already bound to {}",
+                        binding.name(),
+                        key,
+                        other.name()
+                    ));
+                }
+            }
+        }
+        if self.handling.das > 1000 {
+            return Err("handling.das: must be at most 1000 ms".to_owned());
+        }
+        if self.handling.arr > 500 {
+            return Err("handling.arr: must be at most 500 ms".to_owned());
+        }
+        if !(1..=100).contains(&self.handling.sdf) {
+            return Err("handling.sdf: must be between 1 and 100".to_owned());
+        }
+        if self.leaderboard.backend == Backend::Sqlite && !cfg!(feature = "sqlite") {
+            return Err(
+                "leaderboard.backend: this tetris was built without the sqlite feature".to_owned(),
+            );
+        }
+        if self.display.preview > PREVIEW_MAX {
+            return Err(format!("display.preview: must be at most {}", PREVIEW_MAX));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyspec_come_back_from_its_name() {
+        let names = [
+            "h",
+            "H",
+            "+",
+            "ctrl++",
+            "Space",
+            "Left",
+            "Esc",
+            "F5",
+            "ctrl+r",
+            "alt+x",
+            "ctrl+alt+Enter",
+        ];
+        for name in names {
+            let key = KeySpec::try_from(name.to_owned()).unwrap();
+            assert_eq!(key.to_string(), name);
+            assert_eq!(KeySpec::try_from(key.to_string()), Ok(key));
+        }
+        assert_eq!(
+            KeySpec::try_from("CTRL+space".to_owned())
+                .unwrap()
+                .to_string(),
+            "ctrl+Space"
+        );
+    }
+
+    #[test]
+    fn keyspec_refuse_what_it_dont_know() {
+        for name in ["shift+h", "F13", "F0", "home", ""] {
+            assert!(KeySpec::try_from(name.to_owned()).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn validate_the_default_config() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_refuse_a_bad_config() {
+        let mut config = Config::default();
+        config.keys.left = Vec::new();
+        assert!(config.validate().unwrap_err().starts_with("keys.left"));
+
+        let mut config = Config::default();
+        config.keys.left.push(config.keys.rotate[0]);
+        assert!(config.validate().unwrap_err().contains("already bound"));
+
+        let mut config = Config::default();
+        config.handling.das = 1001;
+        assert!(config.validate().unwrap_err().starts_with("handling.das"));
+
+        let mut config = Config::default();
+        config.handling.arr = 501;
+        assert!(config.validate().unwrap_err().starts_with("handling.arr"));
+
+        let mut config = Config::default();
+        config.handling.sdf = 0;
+        assert!(config.validate().unwrap_err().starts_with("handling.sdf"));
+
+        let mut config = Config::default();
+        config.display.preview = PREVIEW_MAX + 1;
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .starts_with("display.preview")
+        );
+    }
+
+    #[test]
+    fn save_then_load_give_the_same_config() {
+        let dir = std::env::temp_dir().join(format!("tetris-config-{}", std::process::id()));
+        let path = dir.join("config.toml");
+        let mut config = Config::default();
+        config.handling.das = 120;
+        config
+            .keys
+            .quit
+            .push(KeySpec::try_from("ctrl+c".to_owned()).unwrap());
+        config.save(&path).unwrap();
+        let loaded = Config::load(&path, true).unwrap();
+        assert_eq!(loaded.handling.das, 120);
+        assert_eq!(loaded.keys.quit, config.keys.quit);
+        // nothing left beside it
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,419 @@
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::bag::*;
+use crate::grid::*;
+use crate::history::{History, Snapshot};
+use crate::input::{Command, Input};
+use crate::menu::Menu;
+use crate::rng::Rng;
+use crate::stats::Stats;
+use crate::tetromino::Tetromino;
+
+// NOTE: need 2 and -2 for the red I,
+// but that would make some tetromino clip through some thin "walls"
+const WALL_KICK_OFFSETS: [i8; 2] = [-1, 1];
+pub const MAX_START_LEVEL: u64 = 15;
+/// version of the game rules, stored in replays
+/// NOTE: bump it whenever `step` (or the bag, rng, scoring...) behave differently,
+/// old replays would desync otherwise
+pub const RULESET: u16 = 3;
+/// lines to clear in sprint
+pub const SPRINT_LINES: u64 = 40;
+/// the engine run at a fixed rate, one `step` per frame
+pub const TARGET_FPS: u32 = 60;
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / TARGET_FPS as u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GameMode {
+    Marathon,
+    /// clear `SPRINT_LINES` as fast as possible
+    Sprint,
+    /// no top out, undo / redo placements
+    Zen,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 3] = [GameMode::Marathon, GameMode::Sprint, GameMode::Zen];
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Sprint => "Sprint",
+            GameMode::Zen => "Zen",
+        }
+    }
+}
+
+/// what the player can do to the game, recorded for replays
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    Left,
+    Right,
+    Rotate,
+    SoftDrop,
+    HardDrop,
+    Undo,
+    Redo,
+}
+
+#[derive(Debug)]
+pub enum GameEvent {
+    GameOver,
+    Quit,
+    Restart,
+    /// save and quit, to resume later
+    Suspend,
+    Tick,
+}
+
+/// a line clear, for the popups
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearKind {
+    pub lines: u8,
+    pub t_spin: bool,
+    /// a tetris or t-spin right after another one
+    pub back_to_back: bool,
+}
+
+impl ClearKind {
+    /// like "TETRIS", "T-SPIN DOUBLE" or "B2B TETRIS"
+    pub fn name(&self) -> String {
+        let lines = ["", "SINGLE", "DOUBLE", "TRIPLE", "TETRIS"][self.lines.min(4) as usize];
+        let mut name = match (self.t_spin, self.lines) {
+            (true, 0) => "T-SPIN".to_owned(),
+            (true, _) => format!("T-SPIN {}", lines),
+            (false, _) => lines.to_owned(),
+        };
+        if self.back_to_back {
+            name = format!("B2B {}", name);
+        }
+        name
+    }
+}
+
+/// what happened in a frame, for the animations
+/// NOTE: nothing in the engine read them, whoever show the game drain them
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// the cells of the tetromino, as (x, y)
+    PieceLocked {
+        cells: Vec<(usize, usize)>,
+    },
+    /// the rows before they collapsed
+    LinesCleared {
+        rows: Vec<usize>,
+        kind: ClearKind,
+    },
+    LevelUp(u64),
+    /// an undo or a redo, the board is not the one the last events were about
+    Rewound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseChoice {
+    Resume,
+    Restart,
+    Suspend,
+    Quit,
+}
+
+pub fn pause_menu() -> Menu<PauseChoice> {
+    Menu::new(
+        "PAUSED",
+        vec![
+            (PauseChoice::Resume, "Resume".to_owned()),
+            (PauseChoice::Restart, "Restart".to_owned()),
+            (PauseChoice::Suspend, "Save and quit".to_owned()),
+            (PauseChoice::Quit, "Quit".to_owned()),
+        ],
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameContext {
+    pub mode: GameMode,
+    pub start_level: u64,
+    pub level: u64,
+    pub score: u64,
+    pub username: String,
+    pub total_lines_cleared: u64,
+    pub grid: Grid,
+    pub bag: Bag,
+    pub history: History,
+    /// gravity timer
+    pub time_since_last_move: Duration,
+    /// game clock, frozen while paused
+    pub play_time: Duration,
+    /// Some while the game is paused
+    #[serde(skip)]
+    pub pause: Option<Menu<PauseChoice>>,
+    pub stats: Stats,
+    /// for t-spin detection
+    pub last_move_rotation: bool,
+    pub seed: u64,
+    pub rng: Rng,
+    /// number of `step` since the start, the replay clock
+    pub frame: u64,
+    /// every action with the frame it happened on
+    pub inputs: Vec<(u64, Action)>,
+    #[serde(skip)]
+    pub events: Vec<EngineEvent>,
+}
+
+impl GameContext {
+    pub fn new(mode: GameMode, start_level: u64, username: String, seed: u64) -> Self {
+        let rng = Rng::new(seed);
+        let mut game_context = GameContext {
+            mode,
+            start_level,
+            level: start_level,
+            score: 0,
+            username,
+            total_lines_cleared: 0,
+            grid: [[None; GRID_WIDTH]; GRID_HEIGHT],
+            bag: Vec::new(),
+            history: History::default(),
+            time_since_last_move: Duration::ZERO,
+            play_time: Duration::ZERO,
+            pause: None,
+            stats: Stats::default(),
+            last_move_rotation: false,
+            seed,
+            rng,
+            frame: 0,
+            inputs: Vec::new(),
+            events: Vec::new(),
+        };
+        refill(&mut game_context.bag, &mut game_context.rng);
+        let snapshot = Snapshot::take(&game_context);
+        game_context.history.push(snapshot);
+        game_context
+    }
+    /// a sprint that reached its goal, not a top out
+    pub fn finished(&self) -> bool {
+        self.mode == GameMode::Sprint && self.total_lines_cleared >= SPRINT_LINES
+    }
+    /// start over with the same player, mode and start level, but a new seed
+    pub fn reset(&mut self) {
+        let username = std::mem::take(&mut self.username);
+        *self = GameContext::new(self.mode, self.start_level, username, rand::random());
+    }
+}
+
+/// one frame for the player: pause menu, focus and keys
+pub fn update(
+    game_context: &mut GameContext,
+    input: &mut Input,
+    event: Option<Event>,
+) -> GameEvent {
+    if let Some(pause_menu) = game_context.pause.as_mut() {
+        if let Some(Event::Key(key)) = event
+            && key.kind != KeyEventKind::Release
+        {
+            if key.code == KeyCode::Esc || input.is_pause(key) {
+                game_context.pause = None;
+                return GameEvent::Tick;
+            }
+            match pause_menu.handle_key(key.code) {
+                Some(PauseChoice::Resume) => game_context.pause = None,
+                Some(PauseChoice::Restart) => return GameEvent::Restart,
+                Some(PauseChoice::Suspend) => return GameEvent::Suspend,
+                Some(PauseChoice::Quit) => return GameEvent::Quit,
+                None => {}
+            }
+        }
+        return GameEvent::Tick;
+    }
+
+    if let Some(Event::FocusLost) = event {
+        input.reset();
+        game_context.pause = Some(pause_menu());
+        return GameEvent::Tick;
+    }
+    match input.update(event, game_context.level) {
+        Some(Command::Quit) => GameEvent::Quit,
+        Some(Command::Pause) => {
+            input.reset();
+            game_context.pause = Some(pause_menu());
+            GameEvent::Tick
+        }
+        Some(Command::Play(action)) => step(game_context, Some(action)),
+        None => step(game_context, None),
+    }
+}
+
+/// one frame of the engine
+/// NOTE: only depend on the context and the action, replays rely on it
+pub fn step(game_context: &mut GameContext, action: Option<Action>) -> GameEvent {
+    if let Some(action) = action {
+        game_context.inputs.push((game_context.frame, action));
+    }
+    game_context.frame += 1;
+    game_context.time_since_last_move += FRAME_DURATION;
+    game_context.play_time += FRAME_DURATION;
+
+    let mut next_tetromino = game_context
+        .bag
+        .last()
+        .expect("bag empty at start of update :/")
+        .clone();
+    let mut rotated = false;
+
+    match action {
+        Some(Action::Left) => next_tetromino.pos.x -= 1,
+        Some(Action::Right) => next_tetromino.pos.x += 1,
+        Some(Action::Rotate) => {
+            next_tetromino.rotate();
+            rotated = true;
+        }
+        Some(Action::SoftDrop) => {
+            // NOTE: not a match guard, a failed guard would still have moved the piece
+            let moved = next_tetromino.try_move_down(&game_context.grid);
+            if moved {
+                game_context.time_since_last_move = Duration::ZERO;
+            }
+        }
+        Some(Action::HardDrop) => return hard_drop(&mut next_tetromino, game_context),
+        Some(Action::Undo) if game_context.mode == GameMode::Zen => {
+            History::undo(game_context);
+            game_context.events.push(EngineEvent::Rewound);
+            return GameEvent::Tick;
+        }
+        Some(Action::Redo) if game_context.mode == GameMode::Zen => {
+            History::redo(game_context);
+            game_context.events.push(EngineEvent::Rewound);
+            return GameEvent::Tick;
+        }
+        _ => {}
+    }
+
+    // sideways collisions
+    if next_tetromino.collide(&game_context.grid) {
+        for x in WALL_KICK_OFFSETS {
+            let mut kicked_tetromino = next_tetromino.clone();
+            kicked_tetromino.pos.x += x;
+            if !kicked_tetromino.collide(&game_context.grid) {
+                // move
+                *game_context.bag.last_mut().expect("bag empty on move") = kicked_tetromino.clone();
+                game_context.last_move_rotation = rotated;
+                return GameEvent::Tick; // skip gravity check for a tick
+            }
+        }
+        return GameEvent::Tick; // skip graviry check for a tick
+    }
+
+    // move down
+    let delay: Duration = get_delay_from_level(game_context.level);
+    if game_context.time_since_last_move >= delay {
+        game_context.time_since_last_move = Duration::ZERO;
+        // ground collision
+        if !next_tetromino.try_move_down(&game_context.grid) {
+            return place_down(game_context, 1.0);
+        }
+    }
+
+    // move
+    let current_tetromino = game_context.bag.last_mut().expect("bag empty on move");
+    if rotated {
+        game_context.last_move_rotation = true;
+    } else if current_tetromino.pos != next_tetromino.pos {
+        game_context.last_move_rotation = false;
+    }
+    *current_tetromino = next_tetromino.clone();
+    GameEvent::Tick
+}
+
+pub fn get_delay_from_level(level: u64) -> Duration {
+    // formula from https://tetris.wiki/Marathon
+    Duration::from_secs_f64((0.8 - ((level as f64 - 1.0) * 0.007)).powf(level as f64 - 1.0))
+}
+
+fn place_down(game_context: &mut GameContext, score_multiplier: f32) -> GameEvent {
+    // place tetromino on grid
+    let tetromino = game_context.bag.pop().expect("bag empty on groud col");
+    let t_spin = game_context.last_move_rotation && tetromino.is_t_spin(&game_context.grid);
+    game_context.last_move_rotation = false;
+    tetromino
+        .stamp_onto(&mut game_context.grid)
+        .expect("tetromino move de-sync");
+    let cells = tetromino
+        .blocks
+        .iter()
+        .map(|block| {
+            (
+                (tetromino.pos.x + block.x) as usize,
+                (tetromino.pos.y + block.y) as usize,
+            )
+        })
+        .collect();
+    game_context.events.push(EngineEvent::PieceLocked { cells });
+
+    refill(&mut game_context.bag, &mut game_context.rng);
+
+    // https://tetris.wiki/Scoring#Recent_guideline_compatible_games
+    let rows = clear_lines(&mut game_context.grid);
+    let lines_cleared_this_frame = rows.len() as u8;
+    if lines_cleared_this_frame > 0 || t_spin {
+        let kind = ClearKind {
+            lines: lines_cleared_this_frame,
+            t_spin,
+            back_to_back: game_context.stats.back_to_back
+                && lines_cleared_this_frame > 0
+                && (lines_cleared_this_frame == 4 || t_spin),
+        };
+        game_context
+            .events
+            .push(EngineEvent::LinesCleared { rows, kind });
+    }
+    match lines_cleared_this_frame {
+        0 => {}
+        1 => game_context.score += (100.0 * game_context.level as f32 * score_multiplier) as u64,
+        2 => game_context.score += (300.0 * game_context.level as f32 * score_multiplier) as u64,
+        3 => game_context.score += (500.0 * game_context.level as f32 * score_multiplier) as u64,
+        4 => game_context.score += (800.0 * game_context.level as f32 * score_multiplier) as u64,
+        _ => {} // TODO: error
+    }
+    game_context
+        .stats
+        .record_placement(lines_cleared_this_frame, t_spin);
+    // https://tetris.wiki/Marathon
+    game_context.total_lines_cleared += lines_cleared_this_frame as u64;
+    if game_context.total_lines_cleared / 10 > game_context.level {
+        game_context.level = game_context.total_lines_cleared / 10;
+        game_context
+            .events
+            .push(EngineEvent::LevelUp(game_context.level));
+    }
+
+    if game_context.finished() {
+        return GameEvent::GameOver;
+    }
+
+    // check if the next tetromino will cause a game over
+    if game_context
+        .bag
+        .last()
+        .expect("bag empty")
+        .collide(&game_context.grid)
+    {
+        if game_context.mode != GameMode::Zen {
+            return GameEvent::GameOver;
+        }
+        // zen top out, just clear the board
+        game_context.grid = [[None; GRID_WIDTH]; GRID_HEIGHT];
+    }
+    if game_context.mode == GameMode::Zen {
+        let snapshot = Snapshot::take(game_context);
+        game_context.history.push(snapshot);
+    }
+    GameEvent::Tick
+}
+
+fn hard_drop(next_tetromino: &mut Tetromino, game_context: &mut GameContext) -> GameEvent {
+    while next_tetromino.try_move_down(&game_context.grid) {
+        game_context.last_move_rotation = false;
+    }
+    *game_context.bag.last_mut().expect("bag empty on move") = next_tetromino.clone();
+    place_down(game_context, 1.0)
+}
@@ -1,29 +1,24 @@
-use ratatui::style::Color;
+use crate::tetromino::PieceKind;
 
 pub const GRID_WIDTH: usize = 10;
 pub const GRID_HEIGHT: usize = 20;
-pub type Grid = [[Option<Color>; GRID_WIDTH]; GRID_HEIGHT];
+/// the kind of the tetromino each cell come from, the theme pick the colors
+pub type Grid = [[Option<PieceKind>; GRID_WIDTH]; GRID_HEIGHT];
 
-pub fn clear_lines(grid: &mut Grid) -> u8 {
-    let mut count = 0;
-    while grid
-        .iter()
-        .any(|line| line.iter().all(|cell| cell.is_some()))
-    {
-        clear_one_line(grid);
-        count += 1;
+/// remove the full lines, return where they were (top to bottom) before the collapse
+pub fn clear_lines(grid: &mut Grid) -> Vec<usize> {
+    let rows: Vec<usize> = (0..GRID_HEIGHT)
+        .filter(|y| grid[*y].iter().all(|cell| cell.is_some()))
+        .collect();
+    // NOTE: top to bottom, removing a line only move the ones above it
+    for row in rows.iter() {
+        clear_one_line(grid, *row);
     }
-    count
+    rows
 }
 
-fn clear_one_line(grid: &mut Grid) -> () {
-    let Some(first_full_line) = grid
-        .iter()
-        .position(|line| line.iter().all(|cell| cell.is_some()))
-    else {
-        return;
-    };
-    for i in (1..=first_full_line).rev() {
+fn clear_one_line(grid: &mut Grid, row: usize) {
+    for i in (1..=row).rev() {
         grid[i] = grid[i - 1];
     }
 }
@@ -0,0 +1,162 @@
+use crate::bag::Bag;
+use serde::{Deserialize, Serialize};
+
+use crate::game::GameContext;
+use crate::grid::Grid;
+use crate::rng::Rng;
+use crate::stats::Stats;
+
+// NOTE: keep it bounded, a long zen session would eat memory otherwise
+const MAX_HISTORY: usize = 1000;
+
+/// state of the game when a tetromino spawn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    grid: Grid,
+    bag: Bag,
+    /// the bags after this one, the same placements get the same pieces again
+    rng: Rng,
+    score: u64,
+    level: u64,
+    total_lines_cleared: u64,
+    stats: Stats,
+}
+
+impl Snapshot {
+    pub fn take(context: &GameContext) -> Self {
+        Snapshot {
+            grid: context.grid,
+            bag: context.bag.clone(),
+            rng: context.rng.clone(),
+            score: context.score,
+            level: context.level,
+            total_lines_cleared: context.total_lines_cleared,
+            stats: context.stats,
+        }
+    }
+    fn restore(&self, context: &mut GameContext) {
+        context.grid = self.grid;
+        context.bag = self.bag.clone();
+        context.rng = self.rng.clone();
+        context.score = self.score;
+        context.level = self.level;
+        context.total_lines_cleared = self.total_lines_cleared;
+        context.stats = self.stats;
+    }
+}
+
+/// undo / redo stacks for zen mode
+/// the top of `undo` is always the spawn of the current tetromino
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    /// call after each spawn, forget the redo branch
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.undo.len() >= MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.undo.push(snapshot);
+        self.redo.clear();
+    }
+    /// go back to the spawn of the previous tetromino
+    /// or reset the current one if there is nothing to undo
+    pub fn undo(context: &mut GameContext) {
+        if context.history.undo.len() > 1 {
+            let current = context.history.undo.pop().expect("undo stack empty");
+            context.history.redo.push(current);
+        }
+        if let Some(snapshot) = context.history.undo.last().cloned() {
+            snapshot.restore(context);
+        }
+    }
+    pub fn redo(context: &mut GameContext) {
+        if let Some(snapshot) = context.history.redo.pop() {
+            snapshot.restore(context);
+            context.history.undo.push(snapshot);
+        }
+    }
+    pub fn undo_len(&self) -> usize {
+        self.undo.len().saturating_sub(1)
+    }
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Action, GameMode, step};
+    use crate::tetromino::PieceKind;
+
+    fn zen() -> GameContext {
+        GameContext::new(GameMode::Zen, 1, "player".to_owned(), 42)
+    }
+
+    /// the pieces that spawn for some hard drops
+    fn drop_pieces(game: &mut GameContext, count: usize) -> Vec<PieceKind> {
+        (0..count)
+            .map(|_| {
+                step(game, Some(Action::HardDrop));
+                game.bag.last().expect("bag empty").kind
+            })
+            .collect()
+    }
+
+    #[test]
+    fn undo_then_redo_come_back_to_the_same_game() {
+        let mut game = zen();
+        drop_pieces(&mut game, 5);
+        let before = Snapshot::take(&game);
+        for _ in 0..3 {
+            History::undo(&mut game);
+        }
+        assert_eq!(game.history.undo_len(), 2);
+        assert_eq!(game.history.redo_len(), 3);
+        for _ in 0..3 {
+            History::redo(&mut game);
+        }
+        let after = Snapshot::take(&game);
+        assert_eq!(after.grid, before.grid);
+        assert_eq!(after.score, before.score);
+        assert_eq!(after.total_lines_cleared, before.total_lines_cleared);
+        assert_eq!(kinds(&after.bag), kinds(&before.bag));
+        assert_eq!(game.history.redo_len(), 0);
+    }
+
+    #[test]
+    fn undo_deal_the_same_pieces_again() {
+        let mut game = zen();
+        // more than a bag ahead, the refills come from the rng
+        let first = drop_pieces(&mut game, 20);
+        for _ in 0..20 {
+            History::undo(&mut game);
+        }
+        assert_eq!(game.history.undo_len(), 0);
+        assert_eq!(drop_pieces(&mut game, 20), first);
+    }
+
+    #[test]
+    fn history_keep_the_last_max_history_spawns() {
+        let mut game = zen();
+        for score in 0..MAX_HISTORY as u64 + 10 {
+            game.score = score;
+            let snapshot = Snapshot::take(&game);
+            game.history.push(snapshot);
+        }
+        assert_eq!(game.history.undo_len(), MAX_HISTORY - 1);
+        for _ in 0..MAX_HISTORY + 10 {
+            History::undo(&mut game);
+        }
+        // the oldest ones were dropped, the first spawn is gone
+        assert_eq!(game.score, 10);
+    }
+
+    fn kinds(bag: &Bag) -> Vec<PieceKind> {
+        bag.iter().map(|tetromino| tetromino.kind).collect()
+    }
+}
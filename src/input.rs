@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use crossterm::event::{Event, KeyEvent, KeyEventKind};
+
+use crate::config::{Binding, Config, Handling, KeyBindings};
+use crate::game::{Action, TARGET_FPS, get_delay_from_level};
+
+/// what a key does in game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Play(Action),
+    Pause,
+    Quit,
+}
+
+fn command_from_binding(binding: Binding) -> Command {
+    match binding {
+        Binding::Left => Command::Play(Action::Left),
+        Binding::Right => Command::Play(Action::Right),
+        Binding::Rotate => Command::Play(Action::Rotate),
+        Binding::SoftDrop => Command::Play(Action::SoftDrop),
+        Binding::HardDrop => Command::Play(Action::HardDrop),
+        Binding::Undo => Command::Play(Action::Undo),
+        Binding::Redo => Command::Play(Action::Redo),
+        Binding::Pause => Command::Pause,
+        Binding::Quit => Command::Quit,
+    }
+}
+
+/// queue an event for the frames to come, the game take one a frame
+/// NOTE: a repeat or a release replace the repeats of that key still queued,
+/// the terminal can repeat a held key faster than that and they would pile up
+pub fn queue_event(queue: &mut VecDeque<Event>, event: Event) {
+    if let Event::Key(key) = &event
+        && key.kind != KeyEventKind::Press
+    {
+        queue.retain(|queued| {
+            !matches!(queued, Event::Key(queued)
+                if queued.code == key.code && queued.kind == KeyEventKind::Repeat)
+        });
+    }
+    queue.push_back(event);
+}
+
+fn ms_to_frames(ms: u64) -> u64 {
+    ms * TARGET_FPS as u64 / 1000
+}
+
+/// turn key events into commands, one per frame at most
+/// NOTE: the actions it produce are what replays record, so DAS / ARR
+/// dont need to be deterministic themself
+pub struct Input {
+    pub bindings: KeyBindings,
+    handling: Handling,
+    /// the terminal report key releases, so held keys repeat here
+    /// instead of relying on the terminal key repeat
+    release_events: bool,
+    /// left or right held, for how many frames
+    shift: Option<(Action, u64)>,
+    /// soft drop held, for how many frames
+    soft_drop: Option<u64>,
+}
+
+impl Input {
+    pub fn new(config: &Config, release_events: bool) -> Self {
+        Input {
+            bindings: config.keys.clone(),
+            handling: config.handling,
+            release_events,
+            shift: None,
+            soft_drop: None,
+        }
+    }
+    /// forget held keys, their release might never come (pause, focus lost)
+    pub fn reset(&mut self) {
+        self.shift = None;
+        self.soft_drop = None;
+    }
+    pub fn is_pause(&self, key: KeyEvent) -> bool {
+        self.bindings.binding_for(key) == Some(Binding::Pause)
+    }
+    /// called once per frame, `level` set the soft drop speed
+    pub fn update(&mut self, event: Option<Event>, level: u64) -> Option<Command> {
+        if let Some(Event::Key(key)) = event
+            && let Some(binding) = self.bindings.binding_for(key)
+        {
+            let command = command_from_binding(binding);
+            match key.kind {
+                KeyEventKind::Press => {
+                    match command {
+                        Command::Play(action @ (Action::Left | Action::Right)) => {
+                            self.shift = Some((action, 0))
+                        }
+                        Command::Play(Action::SoftDrop) => self.soft_drop = Some(0),
+                        _ => {}
+                    }
+                    return Some(command);
+                }
+                // the terminal repeat, only when we cant do better
+                KeyEventKind::Repeat if !self.release_events => return Some(command),
+                KeyEventKind::Repeat => {}
+                KeyEventKind::Release => match command {
+                    Command::Play(action) if self.shift.is_some_and(|(held, _)| held == action) => {
+                        self.shift = None
+                    }
+                    Command::Play(Action::SoftDrop) => self.soft_drop = None,
+                    _ => {}
+                },
+            }
+        }
+        if !self.release_events {
+            return None;
+        }
+
+        if let Some(frames) = self.soft_drop.as_mut() {
+            *frames += 1;
+            let gravity = get_delay_from_level(level).as_secs_f64() * TARGET_FPS as f64;
+            let interval = ((gravity / self.handling.sdf as f64) as u64).max(1);
+            if frames.is_multiple_of(interval) {
+                return Some(Command::Play(Action::SoftDrop));
+            }
+        }
+        if let Some((action, frames)) = self.shift.as_mut() {
+            *frames += 1;
+            let das = ms_to_frames(self.handling.das);
+            let arr = ms_to_frames(self.handling.arr).max(1);
+            if *frames >= das && (*frames - das).is_multiple_of(arr) {
+                return Some(Command::Play(*action));
+            }
+        }
+        None
+    }
+}
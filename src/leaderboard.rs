@@ -1,125 +1,1007 @@
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::GameContext;
+use crate::game::{GameContext, GameMode, RULESET, SPRINT_LINES};
+use crate::replay::{Replay, ReplayError, final_hash, fnv1a};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// rows shown in the game, the player is added below if they are further down
+pub const TOP: usize = 10;
+
+/// one finished game, the tables keep the best of each player
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entry {
     pub level: u64,
     pub score: u64,
     pub username: String,
+    // NOTE: the defaults are for the files from before the tables
+    #[serde(default = "legacy_mode")]
+    pub mode: GameMode,
+    /// 0 for the scores from before the rulesets
+    #[serde(default)]
+    pub ruleset: u16,
+    #[serde(default)]
+    pub lines: u64,
+    #[serde(default)]
+    pub time: Duration,
+    /// unix time, an equal run rank below the one that got there first
+    #[serde(default)]
+    pub date: u64,
+    #[serde(default)]
+    pub pieces: u64,
+    #[serde(default)]
+    pub pps: f64,
+    #[serde(default)]
+    pub seed: u64,
+    /// of the game, `CARGO_PKG_VERSION`, empty before it was stored
+    #[serde(default)]
+    pub version: String,
+    /// file name in the replays dir
+    #[serde(default)]
+    pub replay: Option<String>,
+}
+
+fn legacy_mode() -> GameMode {
+    GameMode::Marathon
 }
 
 impl Entry {
-    pub fn new(context: &GameContext) -> Self {
+    pub fn new(context: &GameContext, replay: Option<String>) -> Self {
         Entry {
             level: context.level,
             score: context.score,
             username: context.username.clone(),
+            mode: context.mode,
+            ruleset: RULESET,
+            lines: context.total_lines_cleared,
+            time: context.play_time,
+            date: now(),
+            pieces: context.stats.pieces,
+            pps: context.stats.pps(context.play_time),
+            seed: context.seed,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            replay,
+        }
+    }
+    pub fn ranked(&self) -> bool {
+        ranked_lines(self.mode).is_some_and(|lines| self.lines >= lines)
+    }
+    /// the same game, seen in two copies of the file
+    fn same_run(&self, other: &Entry) -> bool {
+        self.username == other.username && self.date == other.date && self.seed == other.seed
+    }
+    /// what the table is sorted on, the score or the time
+    pub fn value(&self) -> String {
+        match Ranking::of(self.mode) {
+            Some(Ranking::Time) => format!(
+                "{:02}:{:02}.{:02}",
+                self.time.as_secs() / 60,
+                self.time.as_secs() % 60,
+                self.time.subsec_millis() / 10
+            ),
+            _ => self.score.to_string(),
         }
     }
 }
 
-impl PartialEq for Entry {
-    fn eq(&self, other: &Self) -> bool {
-        return self.score == other.score;
-    }
-    fn ne(&self, other: &Self) -> bool {
-        return self.score != other.score;
+/// what the replay of a run says about it, see `Verifier::verify`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verification {
+    Verified,
+    /// from before the replays, or the replay could not be saved
+    NoReplay,
+    /// missing, unreadable, or of another game
+    BadReplay(String),
+    /// the replay does not end like the run
+    Mismatch(String),
+}
+
+impl Verification {
+    /// in front of the name in the tables
+    pub fn mark(&self) -> char {
+        match self {
+            Verification::Verified => ' ',
+            Verification::NoReplay => '?',
+            Verification::BadReplay(_) | Verification::Mismatch(_) => '!',
+        }
     }
 }
 
-impl PartialOrd for Entry {
-    fn ge(&self, other: &Self) -> bool {
-        return self.score >= other.score;
+impl Display for Verification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verification::Verified => write!(f, "verified"),
+            Verification::NoReplay => write!(f, "no replay"),
+            Verification::BadReplay(why) => write!(f, "bad replay, {}", why),
+            Verification::Mismatch(why) => write!(f, "does not match, {}", why),
+        }
     }
-    fn gt(&self, other: &Self) -> bool {
-        return self.score > other.score;
+}
+
+/// how a replay ends, enough to check the runs that point to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Outcome {
+    username: String,
+    mode: GameMode,
+    seed: u64,
+    score: u64,
+    lines: u64,
+    time: Duration,
+    /// the engine did not end where the replay say it did
+    desync: bool,
+}
+
+impl Outcome {
+    fn new(context: &GameContext, desync: bool) -> Self {
+        Outcome {
+            username: context.username.clone(),
+            mode: context.mode,
+            seed: context.seed,
+            score: context.score,
+            lines: context.total_lines_cleared,
+            time: context.play_time,
+            desync,
+        }
     }
-    fn le(&self, other: &Self) -> bool {
-        return self.score <= other.score;
+}
+
+/// check runs against their replays, each replay is only played once
+/// NOTE: kept by what the replay file hold and not by its name, a replay
+/// written over in the shared dir is played again
+pub struct Verifier {
+    replays: PathBuf,
+    /// where `outcomes` are kept between runs, None to play every replay again
+    /// NOTE: the player own copy, one the others can write could say anything
+    cache: Option<PathBuf>,
+    /// by `replay_key`
+    outcomes: HashMap<String, Outcome>,
+    changed: bool,
+}
+
+impl Verifier {
+    /// NOTE: the cache is only an optimisation, a bad one is ignored
+    pub fn new(replays: &Path, cache: Option<&Path>) -> Self {
+        let outcomes = cache
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Verifier {
+            replays: replays.to_owned(),
+            cache: cache.map(Path::to_path_buf),
+            outcomes,
+            changed: false,
+        }
     }
-    fn lt(&self, other: &Self) -> bool {
-        return self.score < other.score;
+    /// a game that just ended is its own outcome, no need to play it again
+    pub fn remember(&mut self, replay: &Replay, context: &GameContext) {
+        self.outcomes
+            .insert(replay_key(&replay.to_bytes()), Outcome::new(context, false));
+        self.changed = true;
     }
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        if self > other {
-            return Some(std::cmp::Ordering::Greater);
+    /// the replay of the run played again must end with the same score, lines and time
+    /// NOTE: only for ranked runs, a zen game can go on forever
+    pub fn verify(&mut self, entry: &Entry) -> Verification {
+        let Some(name) = &entry.replay else {
+            return Verification::NoReplay;
+        };
+        // NOTE: the scores can be edited by hand, dont follow them out of the replays
+        if Path::new(name).file_name() != Some(OsStr::new(name)) {
+            return Verification::BadReplay(format!("{}: not a replay name", name));
+        }
+        let bytes = match std::fs::read(self.replays.join(name)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return Verification::BadReplay(format!("{}: {}", name, ReplayError::from(e)));
+            }
+        };
+        let key = replay_key(&bytes);
+        if !self.outcomes.contains_key(&key) {
+            let replay = match Replay::parse(&bytes) {
+                Ok(replay) => replay,
+                Err(e) => return Verification::BadReplay(format!("{}: {}", name, e)),
+            };
+            let game = replay.simulate();
+            let desync = final_hash(&game) != replay.final_hash;
+            self.outcomes
+                .insert(key.clone(), Outcome::new(&game, desync));
+            self.changed = true;
+        }
+        let outcome = &self.outcomes[&key];
+        if outcome.username != entry.username
+            || outcome.seed != entry.seed
+            || outcome.mode != entry.mode
+        {
+            return Verification::BadReplay(format!("{}: the replay of another game", name));
         }
-        if self == other {
-            return Some(std::cmp::Ordering::Equal);
+        if outcome.desync {
+            return Verification::Mismatch("the replay desyncs".to_owned());
         }
-        if self < other {
-            return Some(std::cmp::Ordering::Less);
+        let claimed = (entry.score, entry.lines, entry.time);
+        let replayed = (outcome.score, outcome.lines, outcome.time);
+        if claimed != replayed {
+            return Verification::Mismatch(format!(
+                "{} points {} lines in {:.2}s, the replay make {} points {} lines in {:.2}s",
+                entry.score,
+                entry.lines,
+                entry.time.as_secs_f64(),
+                outcome.score,
+                outcome.lines,
+                outcome.time.as_secs_f64()
+            ));
         }
-        return None;
+        Verification::Verified
+    }
+    /// check the runs of a table
+    pub fn verify_rows(&mut self, rows: Vec<(usize, Entry)>) -> Vec<(usize, Entry, Verification)> {
+        rows.into_iter()
+            .map(|(rank, entry)| {
+                let verification = self.verify(&entry);
+                (rank, entry, verification)
+            })
+            .collect()
+    }
+    /// keep the new outcomes for the next time
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let Some(cache) = &self.cache else {
+            return Ok(());
+        };
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(dir) = cache.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(&self.outcomes).map_err(std::io::Error::other)?;
+        write_atomic(cache, &json)?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// what a replay file is cached under, its length and hash
+fn replay_key(bytes: &[u8]) -> String {
+    format!("{}-{:016x}", bytes.len(), fnv1a(bytes))
+}
+
+/// lines a run need to be ranked, None for the modes without a leaderboard
+/// NOTE: zen is practice, and a sprint only count when it is done
+pub fn ranked_lines(mode: GameMode) -> Option<u64> {
+    match mode {
+        GameMode::Marathon => Some(0),
+        GameMode::Sprint => Some(SPRINT_LINES),
+        GameMode::Zen => None,
     }
 }
 
-impl ToString for Entry {
-    fn to_string(&self) -> String {
-        format!(
-            "{: <20}: {: <10} level : {: >3}\n",
-            self.username, self.score, self.level
-        )
+/// how a table is sorted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ranking {
+    /// highest score, then the fastest
+    Score,
+    /// fastest, then the highest score
+    Time,
+}
+
+impl Ranking {
+    /// None for the modes without a leaderboard
+    pub fn of(mode: GameMode) -> Option<Ranking> {
+        match mode {
+            GameMode::Marathon => Some(Ranking::Score),
+            GameMode::Sprint => Some(Ranking::Time),
+            // practice scores dont go on the leaderboard
+            GameMode::Zen => None,
+        }
+    }
+    /// Less when `a` did better, Equal for a tie: they share the rank
+    pub fn result(&self, a: &Entry, b: &Entry) -> Ordering {
+        let by_score = b.score.cmp(&a.score);
+        let by_time = a.time.cmp(&b.time);
+        match self {
+            Ranking::Score => by_score.then(by_time),
+            Ranking::Time => by_time.then(by_score),
+        }
+    }
+    /// the order of the rows, the oldest first in a tie
+    pub fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        self.result(a, b).then(a.date.cmp(&b.date))
     }
 }
 
+/// one leaderboard for the whole machine, like the bsd games
+///
+/// set up by an admin, the game only check it:
+/// ```sh
+/// mkdir -p /var/games/tetris
+/// chown root:games /var/games/tetris
+/// chmod 2775 /var/games/tetris  # setgid, no sticky bit, saves are renames
+/// ```
+/// then add the players to the `games` group, the sqlite backend use `scores.db` in there
+pub const SHARED_DIR: &str = "/var/games/tetris";
+
 #[derive(Debug)]
-pub enum EntryError {
-    EntryNotFount,
+pub enum LeaderboardError {
+    Io(PathBuf, std::io::Error),
+    /// the unreadable file was moved to `backup` (None if that failed too)
+    Corrupted {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+        error: serde_json::Error,
+    },
+    Serialize(serde_json::Error),
+    /// written by a newer tetris, with this version of the file
+    NewerVersion(PathBuf, u16),
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf, rusqlite::Error),
+    /// the shared dir is set up so that it cannot be trusted, or used
+    Shared(PathBuf, String),
+}
+
+impl Display for LeaderboardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LeaderboardError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            LeaderboardError::Corrupted {
+                path,
+                backup,
+                error,
+            } => {
+                // NOTE: on several lines, the title menu is only as wide as its text
+                writeln!(f, "{}: corrupted leaderboard", path.display())?;
+                writeln!(f, "{}", error)?;
+                match backup {
+                    Some(backup) => write!(f, "moved to {}", backup.display()),
+                    None => write!(f, "could not back it up"),
+                }
+            }
+            LeaderboardError::Serialize(e) => write!(f, "cannot serialize leaderboard: {}", e),
+            LeaderboardError::NewerVersion(path, version) => write!(
+                f,
+                "{}: scores version {} is not supported\nthis tetris read up to version {}, update it",
+                path.display(),
+                version,
+                SCORES_VERSION
+            ),
+            #[cfg(feature = "sqlite")]
+            LeaderboardError::Sqlite(path, e) => write!(f, "{}: {}", path.display(), e),
+            LeaderboardError::Shared(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
 }
 
+/// version of the scores file, bump it when `Entry` change in a way the
+/// serde defaults cannot read
+/// NOTE: 0 is the bare array of `{level, score, username}` from before
+pub const SCORES_VERSION: u16 = 1;
+
+/// the scores file
 #[derive(Serialize, Deserialize)]
+struct ScoresFile<E> {
+    version: u16,
+    runs: E,
+}
+
+/// enough to know if the rest can be read
+#[derive(Deserialize)]
+struct Header {
+    version: u16,
+}
+
+#[derive(Default)]
 pub struct LeaderBoard {
     pub entrys: Vec<Entry>,
+    /// read from an older version, backed up before it is written over
+    upgraded_from: Option<u16>,
+    /// added since the file was read, the only runs a save bring to it
+    /// NOTE: not all of `entrys`, a run the others removed would come back
+    pending: Vec<Entry>,
 }
 impl LeaderBoard {
-    pub fn load(path: &str) -> Self {
-        let string: String = std::fs::read_to_string(path).expect("cannot load save file");
-        Self {
-            entrys: serde_json::from_str(&string).expect("error serialising")
-            // .unwrap_or(Vec::new()), // unwrap_or(vec![Entry {username: "failed to load save file".to_owned(), score: 42, level: 42,}])
+    /// an empty leaderboard if the file dont exist yet
+    /// NOTE: a corrupted file is moved aside, so the next save dont overwrite it
+    pub fn load(path: &Path) -> Result<Self, LeaderboardError> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(LeaderboardError::Io(path.to_owned(), e)),
+        };
+        // an empty file (touch .scores) is an empty leaderboard too
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Self::default());
         }
+        let corrupted = |error| LeaderboardError::Corrupted {
+            path: path.to_owned(),
+            backup: backup(path),
+            error,
+        };
+        let first = bytes.iter().find(|byte| !byte.is_ascii_whitespace());
+        if first == Some(&b'[') {
+            return Ok(LeaderBoard {
+                entrys: serde_json::from_slice(&bytes).map_err(corrupted)?,
+                upgraded_from: Some(0),
+                pending: Vec::new(),
+            });
+        }
+        let header: Header = serde_json::from_slice(&bytes).map_err(corrupted)?;
+        // NOTE: not corrupted, just not for us, so it stay where it is
+        if header.version > SCORES_VERSION {
+            return Err(LeaderboardError::NewerVersion(
+                path.to_owned(),
+                header.version,
+            ));
+        }
+        let file: ScoresFile<Vec<Entry>> = serde_json::from_slice(&bytes).map_err(corrupted)?;
+        Ok(LeaderBoard {
+            entrys: file.runs,
+            upgraded_from: (file.version < SCORES_VERSION).then_some(file.version),
+            pending: Vec::new(),
+        })
     }
-    pub fn save(self, path: &str) {
-        let string = serde_json::to_string(&self).expect("failed to serialize");
-        println!("saving {}", string);
-        println!("leaderboraed {}", self.to_string());
-        std::fs::write(path, string).expect("failed to write to file");
+    /// add our new runs to what the other players saved since we loaded it, then write it
+    /// NOTE: the file is shared, so under an advisory lock, and replaced by a rename
+    /// so a reader never see half of it
+    pub fn save(&mut self, path: &Path) -> Result<(), LeaderboardError> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| LeaderboardError::Io(dir.to_owned(), e))?;
+        }
+        let _lock = lock(path)?;
+        let mut merged = match LeaderBoard::load(path) {
+            Ok(leaderboard) => leaderboard,
+            // it was moved aside, ours is all we have
+            Err(LeaderboardError::Corrupted { .. }) => LeaderBoard {
+                entrys: self.entrys.clone(),
+                ..Default::default()
+            },
+            Err(e) => return Err(e),
+        };
+        // keep the old file, in case this version got something wrong
+        if let Some(version) = merged.upgraded_from {
+            let mut name = path.file_name().unwrap_or_default().to_owned();
+            name.push(format!(".v{}.bak", version));
+            let backup = path.with_file_name(name);
+            if !backup.exists() {
+                std::fs::copy(path, &backup).map_err(|e| LeaderboardError::Io(backup, e))?;
+            }
+        }
+        for entry in self.pending.iter() {
+            merged.merge_entry(entry.clone());
+        }
+        let file = ScoresFile {
+            version: SCORES_VERSION,
+            runs: &merged.entrys,
+        };
+        let string = serde_json::to_string(&file).map_err(LeaderboardError::Serialize)?;
+        write_atomic(path, &string).map_err(|e| LeaderboardError::Io(path.to_owned(), e))?;
+        // NOTE: only once it is written, a failed save keep them for the next one
+        merged.upgraded_from = None;
+        *self = merged;
+        Ok(())
+    }
+    /// the runs only one of the copies has
+    fn merge_entry(&mut self, entry: Entry) {
+        if !self.entrys.iter().any(|run| run.same_run(&entry)) {
+            self.entrys.push(entry);
+        }
     }
+    /// a new run, written by the next `save`
     pub fn add_entry(&mut self, entry: Entry) {
+        self.pending.push(entry.clone());
         self.entrys.push(entry);
     }
-    pub fn get_entry(&mut self, username: &str) -> Option<&mut Entry> {
-        for entry in self.entrys.iter_mut() {
-            if entry.username == username {
-                return Some(entry);
+    /// the personal best of a player
+    pub fn best(&self, username: &str, mode: GameMode, ruleset: u16) -> Option<&Entry> {
+        let ranking = Ranking::of(mode)?;
+        self.entrys
+            .iter()
+            .filter(|entry| {
+                entry.username == username
+                    && entry.mode == mode
+                    && entry.ruleset == ruleset
+                    && entry.ranked()
+            })
+            .min_by(|a, b| ranking.compare(a, b))
+    }
+    /// the personal bests of one mode and ruleset, best first with their rank
+    /// NOTE: ties share the rank, see `Ranking::result`
+    pub fn table(&self, mode: GameMode, ruleset: u16) -> Vec<(usize, &Entry)> {
+        let Some(ranking) = Ranking::of(mode) else {
+            return Vec::new();
+        };
+        // the best of each player in one pass, the first one in a full tie like `best`
+        let mut bests: HashMap<&str, &Entry> = HashMap::new();
+        for entry in self.entrys.iter() {
+            if entry.mode != mode || entry.ruleset != ruleset || !entry.ranked() {
+                continue;
             }
+            bests
+                .entry(&entry.username)
+                .and_modify(|best| {
+                    if ranking.compare(entry, best) == Ordering::Less {
+                        *best = entry;
+                    }
+                })
+                .or_insert(entry);
         }
-        None
+        let mut entries: Vec<&Entry> = bests.into_values().collect();
+        entries.sort_by(|a, b| {
+            ranking
+                .compare(a, b)
+                .then_with(|| a.username.cmp(&b.username))
+        });
+        let mut table: Vec<(usize, &Entry)> = Vec::with_capacity(entries.len());
+        for (i, entry) in entries.into_iter().enumerate() {
+            let rank = match table.last() {
+                Some((rank, previous)) if ranking.result(previous, entry) == Ordering::Equal => {
+                    *rank
+                }
+                _ => i + 1,
+            };
+            table.push((rank, entry));
+        }
+        table
+    }
+    /// every mode and ruleset that has ranked runs, newest ruleset first
+    pub fn tables(&self) -> Vec<(GameMode, u16)> {
+        let mut keys: Vec<(GameMode, u16)> = Vec::new();
+        for entry in self.entrys.iter().filter(|entry| entry.ranked()) {
+            if !keys.contains(&(entry.mode, entry.ruleset)) {
+                keys.push((entry.mode, entry.ruleset));
+            }
+        }
+        sort_tables(&mut keys);
+        keys
+    }
+    /// position of a run among the bests of the other players, 1 is the best
+    pub fn rank(&self, entry: &Entry) -> usize {
+        let Some(ranking) = Ranking::of(entry.mode) else {
+            return 1;
+        };
+        1 + self
+            .table(entry.mode, entry.ruleset)
+            .iter()
+            .filter(|(_, best)| {
+                best.username != entry.username && ranking.result(best, entry) == Ordering::Less
+            })
+            .count()
     }
-    pub fn update_entry(
-        &mut self,
+    /// the first `top` rows of a table, and the row of `username` if it is further down
+    pub fn top(
+        &self,
+        mode: GameMode,
+        ruleset: u16,
+        top: usize,
         username: &str,
-        score: u64,
-        level: u64,
-    ) -> Result<(), EntryError> {
-        let entry: &mut Entry = self.get_entry(username).ok_or(EntryError::EntryNotFount)?;
-        if score > entry.score {
-            entry.level = level;
-            entry.score = score;
-        }
-        println!("updated entry: {}", entry.to_string());
-        Ok(())
+    ) -> Vec<(usize, Entry)> {
+        self.table(mode, ruleset)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (_, entry))| *i < top || entry.username == username)
+            .map(|(_, (rank, entry))| (rank, entry.clone()))
+            .collect()
+    }
+    /// every run of a player, oldest first
+    pub fn runs(&self, username: &str) -> Vec<Entry> {
+        let mut runs: Vec<Entry> = self
+            .entrys
+            .iter()
+            .filter(|entry| entry.username == username)
+            .cloned()
+            .collect();
+        runs.sort_by_key(|entry| entry.date);
+        runs
     }
 }
 
-impl ToString for LeaderBoard {
-    fn to_string(&self) -> String {
-        self.entrys
+/// newest ruleset first, then in the order of the modes
+pub fn sort_tables(keys: &mut [(GameMode, u16)]) {
+    keys.sort_by_key(|(mode, ruleset)| {
+        let index = GameMode::ALL.iter().position(|m| m == mode);
+        (std::cmp::Reverse(*ruleset), index)
+    });
+}
+
+/// rows from `top` checked with `Verifier::verify_rows`, with a "..." before the row of the
+/// player if it is past `top`
+pub fn format_rows(rows: &[(usize, Entry, Verification)], top: usize) -> String {
+    if rows.is_empty() {
+        return "no entries yet".to_owned();
+    }
+    let mut string = String::new();
+    for (i, (rank, entry, verification)) in rows.iter().enumerate() {
+        if i == top {
+            string += "  ...\n";
+        }
+        // NOTE: fits in the side panel
+        let name: String = entry.username.chars().take(10).collect();
+        string += &format!(
+            "{: >2}{}{: <10} {: >8}\n",
+            rank,
+            verification.mark(),
+            name,
+            entry.value()
+        );
+    }
+    if rows
+        .iter()
+        .any(|(_, _, verification)| *verification != Verification::Verified)
+    {
+        string += "\n? no replay  ! bad replay\n";
+    }
+    string
+}
+
+/// the runs of a player from `runs`, with a `*` on each new personal best
+pub fn format_history(runs: &[Entry]) -> String {
+    let mut string = String::new();
+    for mode in GameMode::ALL {
+        let mut rulesets: Vec<u16> = runs
             .iter()
-            .map(|e| e.to_string())
-            .collect::<Vec<String>>()
-            .concat()
+            .filter(|entry| entry.mode == mode)
+            .map(|entry| entry.ruleset)
+            .collect();
+        rulesets.sort_by(|a, b| b.cmp(a));
+        rulesets.dedup();
+        for ruleset in rulesets {
+            string += &format!("{} (ruleset {})\n", mode.name(), ruleset);
+            string += &format!(
+                "  {: <16} {: >8} {: >5} {: >6} {: >5}  {}\n",
+                "date", "result", "lines", "pieces", "pps", "replay"
+            );
+            let mut best: Option<&Entry> = None;
+            for entry in runs
+                .iter()
+                .filter(|entry| entry.mode == mode && entry.ruleset == ruleset)
+            {
+                let improved = match (Ranking::of(mode), best) {
+                    _ if !entry.ranked() => false,
+                    (Some(ranking), Some(best)) => ranking.compare(entry, best) == Ordering::Less,
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                };
+                if improved {
+                    best = Some(entry);
+                }
+                string += &format!(
+                    "{} {: <16} {: >8} {: >5} {: >6} {: >5.2}  {}\n",
+                    if improved { "*" } else { " " },
+                    format_date(entry.date),
+                    entry.value(),
+                    entry.lines,
+                    entry.pieces,
+                    entry.pps,
+                    entry.replay.as_deref().unwrap_or("-")
+                );
+            }
+            string += "\n";
+        }
+    }
+    string
+}
+
+/// "YYYY-MM-DD HH:MM" in UTC, "-" for the runs from before the dates
+pub fn format_date(date: u64) -> String {
+    if date == 0 {
+        return "-".to_owned();
+    }
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = date / 86400;
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let minutes = date % 86400 / 60;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// `<file>.corrupted-<unix time>` next to it, None if it cannot be moved
+fn backup(path: &Path) -> Option<PathBuf> {
+    let mut name = path.file_name()?.to_owned();
+    name.push(format!(".corrupted-{}", now()));
+    let backup = path.with_file_name(name);
+    std::fs::rename(path, &backup).ok()?;
+    Some(backup)
+}
+
+/// exclusive lock on `<file>.lock`, released when dropped
+/// NOTE: not the scores file itself, the rename replace it with another inode
+fn lock(path: &Path) -> Result<File, LeaderboardError> {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".lock");
+    let lock_path = path.with_file_name(name);
+    // created by whoever saved first, the others may only be able to read it
+    // NOTE: flock dont care how the file was opened
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .or_else(|_| File::open(&lock_path))
+        .map_err(|e| LeaderboardError::Io(lock_path.clone(), e))?;
+    file.lock()
+        .map_err(|e| LeaderboardError::Io(lock_path.clone(), e))?;
+    Ok(file)
+}
+
+/// write a temp file next to `path` and rename it over, with the same permissions
+pub(crate) fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".tmp-{}", std::process::id()));
+    let temp_path = path.with_file_name(name);
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        // the other players need to keep writing to a shared file
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// the shared dir is owned by root and writable by its group only, and the
+/// scores file in it is a plain file of that group
+pub fn check_shared(dir: &Path, scores: &Path) -> Result<(), LeaderboardError> {
+    let fail = |path: &Path, message: &str| {
+        Err(LeaderboardError::Shared(
+            path.to_owned(),
+            message.to_owned(),
+        ))
+    };
+    let metadata = std::fs::metadata(dir).map_err(|e| LeaderboardError::Io(dir.to_owned(), e))?;
+    let mode = metadata.permissions().mode();
+    if !metadata.is_dir() {
+        return fail(dir, "not a directory");
+    }
+    if metadata.uid() != 0 {
+        return fail(dir, "must be owned by root");
+    }
+    if mode & 0o020 == 0 {
+        return fail(dir, "must be group writable (chmod 2775)");
+    }
+    if mode & 0o002 != 0 {
+        return fail(dir, "must not be world writable (chmod 2775)");
+    }
+    // NOTE: the new files would not get the group, and a sticky dir refuse
+    // the rename over a file of someone else
+    if mode & 0o2000 == 0 {
+        return fail(dir, "must be setgid (chmod 2775)");
+    }
+    if mode & 0o1000 != 0 {
+        return fail(dir, "must not be sticky (chmod 2775)");
+    }
+    let in_group = users::get_current_uid() == 0
+        || users::get_effective_gid() == metadata.gid()
+        || users::group_access_list()
+            .map(|groups| groups.iter().any(|group| group.gid() == metadata.gid()))
+            .unwrap_or(false);
+    if !in_group {
+        let group = users::get_group_by_gid(metadata.gid())
+            .map(|group| group.name().to_string_lossy().into_owned())
+            .unwrap_or_else(|| metadata.gid().to_string());
+        return fail(dir, &format!("you are not in the {} group", group));
+    }
+    match std::fs::symlink_metadata(scores) {
+        Ok(file) if !file.is_file() => fail(scores, "not a regular file"),
+        Ok(file) if file.gid() != metadata.gid() => {
+            fail(scores, "not in the group of the directory")
+        }
+        Ok(file) if file.permissions().mode() & 0o002 != 0 => {
+            fail(scores, "must not be world writable")
+        }
+        Ok(_) => Ok(()),
+        // the first save create it
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(LeaderboardError::Io(scores.to_owned(), e)),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// a ranked marathon run, the date is enough to tell them apart
+    pub(crate) fn run(username: &str, score: u64, date: u64) -> Entry {
+        Entry {
+            level: 1,
+            score,
+            username: username.to_owned(),
+            mode: GameMode::Marathon,
+            ruleset: RULESET,
+            lines: 10,
+            time: Duration::from_secs(60),
+            date,
+            pieces: 30,
+            pps: 0.5,
+            seed: date,
+            version: String::new(),
+            replay: None,
+        }
+    }
+
+    /// an empty dir for one test
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tetris-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("cannot create the test dir");
+        dir
+    }
+
+    fn dates(leaderboard: &LeaderBoard) -> Vec<u64> {
+        let mut dates: Vec<u64> = leaderboard.entrys.iter().map(|entry| entry.date).collect();
+        dates.sort();
+        dates
+    }
+
+    #[test]
+    fn upgrade_the_bare_array_and_back_it_up() {
+        let path = temp_dir("upgrade").join("scores");
+        let legacy = r#"[{"level":2,"score":100,"username":"bob"}]"#;
+        std::fs::write(&path, legacy).unwrap();
+
+        let mut leaderboard = LeaderBoard::load(&path).unwrap();
+        assert_eq!(leaderboard.upgraded_from, Some(0));
+        let bob = &leaderboard.entrys[0];
+        assert_eq!((bob.username.as_str(), bob.score), ("bob", 100));
+        assert_eq!((bob.mode, bob.ruleset), (GameMode::Marathon, 0));
+
+        leaderboard.add_entry(run("alice", 200, 1));
+        leaderboard.save(&path).unwrap();
+        let backup = path.with_file_name("scores.v0.bak");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), legacy);
+        let header: Header = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(header.version, SCORES_VERSION);
+        let reloaded = LeaderBoard::load(&path).unwrap();
+        assert_eq!(reloaded.upgraded_from, None);
+        assert_eq!(reloaded.entrys.len(), 2);
+    }
+
+    #[test]
+    fn refuse_a_newer_version() {
+        let path = temp_dir("newer").join("scores");
+        let newer = format!(r#"{{"version":{},"runs":[]}}"#, SCORES_VERSION + 1);
+        std::fs::write(&path, &newer).unwrap();
+
+        assert!(matches!(
+            LeaderBoard::load(&path),
+            Err(LeaderboardError::NewerVersion(_, version)) if version == SCORES_VERSION + 1
+        ));
+        let mut leaderboard = LeaderBoard::default();
+        leaderboard.add_entry(run("alice", 100, 1));
+        assert!(leaderboard.save(&path).is_err());
+        // not moved aside like a corrupted one, and not written over
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), newer);
+    }
+
+    #[test]
+    fn ties_share_the_rank_and_the_date_only_order_them() {
+        let mut leaderboard = LeaderBoard::default();
+        for entry in [
+            run("bob", 100, 2),
+            run("alice", 100, 5),
+            run("dave", 50, 3),
+            run("carol", 200, 9),
+            // alice did it first, that is her best
+            run("alice", 100, 1),
+            run("carol", 150, 4),
+        ] {
+            leaderboard.add_entry(entry);
+        }
+        let mut sprint = run("erin", 1000, 6);
+        sprint.mode = GameMode::Sprint;
+        leaderboard.add_entry(sprint);
+
+        let table: Vec<(usize, &str, u64)> = leaderboard
+            .table(GameMode::Marathon, RULESET)
+            .into_iter()
+            .map(|(rank, entry)| (rank, entry.username.as_str(), entry.date))
+            .collect();
+        assert_eq!(
+            table,
+            [
+                (1, "carol", 9),
+                (2, "alice", 1),
+                (2, "bob", 2),
+                (4, "dave", 3)
+            ]
+        );
+        // a new run tied with them is not behind them either
+        assert_eq!(leaderboard.rank(&run("frank", 100, 10)), 2);
+    }
+
+    #[test]
+    fn save_dont_bring_back_a_removed_run() {
+        let path = temp_dir("removed").join("scores");
+        let mut first = LeaderBoard::default();
+        first.add_entry(run("alice", 100, 1));
+        first.add_entry(run("bob", 200, 2));
+        first.save(&path).unwrap();
+
+        let mut ours = LeaderBoard::load(&path).unwrap();
+        // someone else drop bob's run in the meantime
+        let mut theirs = LeaderBoard::load(&path).unwrap();
+        theirs.entrys.retain(|entry| entry.username != "bob");
+        let file = ScoresFile {
+            version: SCORES_VERSION,
+            runs: &theirs.entrys,
+        };
+        write_atomic(&path, &serde_json::to_string(&file).unwrap()).unwrap();
+
+        ours.add_entry(run("carol", 300, 3));
+        ours.save(&path).unwrap();
+        assert_eq!(dates(&ours), [1, 3]);
+        assert_eq!(dates(&LeaderBoard::load(&path).unwrap()), [1, 3]);
+    }
+
+    #[test]
+    fn failed_write_leave_the_old_file() {
+        let path = temp_dir("failed-write").join("scores");
+        let mut leaderboard = LeaderBoard::default();
+        leaderboard.add_entry(run("alice", 100, 1));
+        leaderboard.save(&path).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        // the temp file cannot be created where a dir is
+        let temp = path.with_file_name(format!("scores.tmp-{}", std::process::id()));
+        std::fs::create_dir(&temp).unwrap();
+        leaderboard.add_entry(run("bob", 200, 2));
+        assert!(matches!(
+            leaderboard.save(&path),
+            Err(LeaderboardError::Io(..))
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+
+        // the run is still there for the next save
+        std::fs::remove_dir(&temp).unwrap();
+        leaderboard.save(&path).unwrap();
+        assert_eq!(dates(&LeaderBoard::load(&path).unwrap()), [1, 2]);
+    }
+
+    #[test]
+    fn save_wait_for_the_lock() {
+        let path = temp_dir("lock").join("scores");
+        let held = lock(&path).unwrap();
+        let saver = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                let mut leaderboard = LeaderBoard::default();
+                leaderboard.add_entry(run("alice", 100, 1));
+                leaderboard.save(&path)
+            }
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!path.exists(), "saved without the lock");
+        drop(held);
+        saver.join().unwrap().unwrap();
+        assert_eq!(dates(&LeaderBoard::load(&path).unwrap()), [1]);
     }
 }
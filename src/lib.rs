@@ -0,0 +1,33 @@
+//! tetris in the terminal
+//!
+//! the game can be embedded in other ratatui apps, see [`widget::TetrisWidget`]
+//! and [`widget::Tetris`]
+
+pub mod animation;
+pub mod bag;
+pub mod board;
+pub mod color;
+pub mod config;
+pub mod game;
+pub mod grid;
+pub mod history;
+pub mod input;
+pub mod leaderboard;
+pub mod menu;
+pub mod render;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod screen;
+pub mod stats;
+pub mod storage;
+pub mod tetromino;
+pub mod theme;
+pub mod vec2;
+pub mod widget;
+
+pub const CREDITS: &str = "Tetris
+Author : mphippen
+Source : https://github.com/PurpleProg/tetris
+
+rust > C";
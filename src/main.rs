@@ -1,360 +1,726 @@
-use crossterm::event::{self, Event, KeyCode};
-use leaderboard::Entry;
-use ratatui::{
-    DefaultTerminal,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
-    symbols,
-    widgets::{Block, Borders, Paragraph},
+use crossterm::event::{
+    self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyEventKind,
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
 };
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use tetromino::Tetromino;
+use tetris::animation::Animations;
+use tetris::color::ColorSupport;
+use tetris::config::{self, Backend, Config, Handling};
+use tetris::game::{self, GameContext, GameEvent, GameMode, RULESET, TARGET_FPS, update};
+use tetris::input::{Input, queue_event};
+use tetris::leaderboard::*;
+use tetris::replay::{Replay, ReplayViewer};
+use tetris::screen::{
+    ModeChoice, ModeSelect, Results, ResultsChoice, Screen, Settings, SettingsChoice, TitleChoice,
+};
+use tetris::storage::{JsonFile, Storage};
+use tetris::theme::Theme;
+use tetris::{CREDITS, render, save};
 use users::get_current_username;
 
-mod bag;
-mod grid;
-mod leaderboard;
-mod tetromino;
-mod vec2;
-use crate::bag::*;
-use crate::grid::*;
-use crate::leaderboard::*;
-
-const TARGET_FPS: u8 = 60;
-// NOTE: need 2 and -2 for the red I,
-// but that would make some tetromino clip through some thin "walls"
-const WALL_KICK_OFFSETS: [i8; 2] = [-1, 1];
-const CREDITS: &str = "Tetris
-Author : mphippen
-Source : https://github.com/PurpleProg/tetris
-
-rust > C";
-
-#[derive(Debug)]
-enum GameEvent {
-    GameOver,
-    Quit,
-    Tick,
-}
-
-struct GameContext {
-    level: u64,
-    score: u64,
-    username: String,
-    total_lines_cleared: u64,
-    grid: Grid,
-    bag: Bag,
-    leaderboard: LeaderBoard,
-}
-
+/// without a home, in the working directory like before
+/// NOTE: it was the default for everyone, it is imported into the data dir
+const SCORES_PATH: &str = ".scores";
+/// overrides the config, not the --scores flag
+const SCORES_ENV: &str = "TETRIS_SCORES";
 // TODO:
 // fix speed too slow at startup
-// save score -> leaderboard NOTE: very fun ! but easy to cheat
-// bag preview -> next piece preview (anoying as fuck cause i have to pre-shot the next bag) or no ? if i refill when size is one
-// gameover -> replay ?
+// save score -> leaderboard NOTE: very fun ! the replays keep it honest, see `tetris verify`
 // remove expects (rendererrors)
 // ccw rotate
-// preview, hold
-
-fn main() -> () {
-    let mut terminal = ratatui::init();
+// hold
+
+/// where the data files go, replays and saves live next to the scores
+struct Paths {
+    scores: PathBuf,
+    replays: PathBuf,
+    saves: PathBuf,
+    /// what the replays gave, always in the player data dir, see `Verifier`
+    /// None without a home, the replays are played again each time
+    verified: Option<PathBuf>,
+    /// an old `SCORES_PATH` to import, the scores in the data dir dont exist yet
+    legacy: Option<PathBuf>,
+}
 
-    // setup game vars
-    let mut game_context = GameContext {
-        level: 1,
-        score: 0,
-        username: get_current_username()
-            .unwrap_or("User not found".into())
-            .into_string()
-            .expect("error converting OsString to String"),
-        total_lines_cleared: 0,
-        grid: [[None; GRID_WIDTH]; GRID_HEIGHT],
-        bag: new_bag(),
-        leaderboard: LeaderBoard::load(".scores"),
-    };
-    if game_context
-        .leaderboard
-        .get_entry(&game_context.username)
-        .is_none()
-    {
-        game_context
-            .leaderboard
-            .add_entry(Entry::new(&game_context));
+impl Paths {
+    fn new(scores: PathBuf, data_dir: Option<&Path>) -> Self {
+        let dir = scores.parent().map(Path::to_path_buf).unwrap_or_default();
+        Paths {
+            scores,
+            replays: dir.join(".replays"),
+            saves: dir.join(".saves"),
+            verified: data_dir.map(|dir| dir.join(".verified")),
+            legacy: None,
+        }
     }
-
-    let mut delta_time: Duration;
-    let mut previous_time = Instant::now();
-    let mut time_since_last_move = Duration::new(0, 0);
-
-    let tick_rate: Duration = Duration::from_secs_f32(1.0 / TARGET_FPS as f32);
-
-    let mut frame_time: Duration = Duration::new(0, 0);
-
-    'gameloop: loop {
-        delta_time = Instant::now() - previous_time;
-        time_since_last_move += delta_time;
-        frame_time += delta_time;
-        previous_time = Instant::now();
-        if frame_time < tick_rate {
-            std::thread::sleep(tick_rate - frame_time);
-            frame_time = Duration::ZERO;
-            continue 'gameloop;
+    /// the scores and replays for everyone, the saves stay with the player
+    /// NOTE: replays right in the dir, a sub dir would get the umask of whoever made it
+    fn shared(dir: &Path, data_dir: Option<&Path>, backend: Backend) -> Self {
+        Paths {
+            scores: dir.join(backend.file_name()),
+            replays: dir.to_owned(),
+            saves: data_dir.unwrap_or(Path::new("")).join(".saves"),
+            verified: data_dir.map(|dir| dir.join(".verified")),
+            legacy: None,
         }
-
-        match update(&mut game_context, &mut time_since_last_move) {
-            GameEvent::GameOver => {
-                // NOTE: gameover logic here ?
-                // could use it's own function
-                ratatui::restore();
-                println!("Game Over :(");
-                game_context.leaderboard.save(".scores");
-                println!(
-                    "Score: {}, level: {}",
-                    game_context.score, game_context.level
-                );
-                return;
+    }
+    /// --scores, then $TETRIS_SCORES, then the config, then the shared dir if
+    /// there is one, then the data dir
+    fn resolve(flag: Option<PathBuf>, config: &Config, warnings: &mut Vec<String>) -> Self {
+        let explicit = flag
+            .or_else(|| {
+                std::env::var_os(SCORES_ENV)
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+            })
+            .or_else(|| config.leaderboard.path.clone());
+        let data_dir = config::data_dir();
+        if let Some(scores) = explicit {
+            return Paths::new(scores, data_dir.as_deref());
+        }
+        let backend = config.leaderboard.backend;
+        let shared_dir = Path::new(SHARED_DIR);
+        if shared_dir.is_dir() {
+            let shared = Paths::shared(shared_dir, data_dir.as_deref(), backend);
+            match check_shared(shared_dir, &shared.scores) {
+                Ok(()) => return shared,
+                Err(e) => warnings.push(format!("{}\nusing your own leaderboard", e)),
             }
-            GameEvent::Tick => {}
-            GameEvent::Quit => break 'gameloop,
         }
-        // let _ = game_context
-        //     .leaderboard
-        //     .update_entry(
-        //         &game_context.username,
-        //         game_context.score,
-        //         game_context.level,
-        //     )
-        //     .expect("entry not found for update");
-        render(&game_context, &mut terminal);
+        let Some(data_dir) = data_dir else {
+            return Paths::new(PathBuf::from(SCORES_PATH), None);
+        };
+        let mut paths = Paths::new(data_dir.join(backend.file_name()), Some(&data_dir));
+        let legacy = Path::new(SCORES_PATH);
+        if !paths.scores.exists() && legacy.is_file() {
+            paths.legacy = Some(legacy.to_owned());
+        }
+        paths
     }
-    // NOTE: quit logic here ?
-    // could use it's own function too
-    ratatui::restore();
-    let _ = game_context
-        .leaderboard
-        .update_entry(
-            &game_context.username,
-            game_context.score,
-            game_context.level,
-        )
-        .expect("entry not found for update");
-    game_context.leaderboard.save(".scores");
-    println!(
-        "Score: {}, level: {}",
-        game_context.score, game_context.level
-    );
 }
 
-fn update(game_context: &mut GameContext, time_since_last_move: &mut Duration) -> GameEvent {
-    let mut next_tetromino = game_context
-        .bag
-        .last()
-        .expect("bag empty at start of update :/")
-        .clone();
+/// the leaderboard on the side of the game
+struct Panel {
+    mode: GameMode,
+    username: String,
+    rows: Vec<(usize, Entry, Verification)>,
+}
 
-    if event::poll(Duration::from_secs(0)).unwrap_or(false) {
-        if let Ok(Event::Key(key)) = event::read() {
-            match key.code {
-                KeyCode::Esc => return GameEvent::Quit,
-                KeyCode::Left => next_tetromino.pos.x -= 1,
-                KeyCode::Right => next_tetromino.pos.x += 1,
-                KeyCode::Up => next_tetromino.rotate(), // hard drop
-                KeyCode::Down => return hard_drop(&mut next_tetromino, game_context), // soft drop
-                KeyCode::Char(' ') => return hard_drop(&mut next_tetromino, game_context),
-                // z cw
-                // x 180
-                // c ccw
-                // s hold piece
-                // vim keys
-                KeyCode::Char('h') => next_tetromino.pos.x -= 1,
-                KeyCode::Char('l') => next_tetromino.pos.x += 1,
-                KeyCode::Char('k') => next_tetromino.rotate(),
-                KeyCode::Char('j') => return hard_drop(&mut next_tetromino, game_context),
+/// everything that outlive a single game
+struct App {
+    screen: Screen,
+    username: String,
+    storage: Box<dyn Storage>,
+    /// queried again when the mode or the player change
+    panel: Option<Panel>,
+    verifier: Verifier,
+    config: Config,
+    /// where the settings screen save the config, None without a home
+    config_path: Option<PathBuf>,
+    theme: Theme,
+    input: Input,
+    paths: Paths,
+    animations: Animations,
+    /// terminal size, kept up to date by resize events
+    size: (u16, u16),
+    /// shown under the title menu until a key is pressed, and on exit
+    warning: Option<String>,
+    /// the game being played came from the save, delete it when the game end
+    resumed: bool,
+}
+
+impl App {
+    /// return false to exit
+    fn update(&mut self, event: Option<Event>) -> bool {
+        if let Some(Event::Resize(width, height)) = event {
+            self.size = (width, height);
+        }
+        // nobody can play what they cant see
+        if render::too_small(self.size.0, self.size.1) {
+            match &mut self.screen {
+                Screen::Game(game_context) if game_context.pause.is_none() => {
+                    self.input.reset();
+                    game_context.pause = Some(game::pause_menu());
+                }
+                Screen::Replay(replay_viewer) => replay_viewer.paused = true,
                 _ => {}
             }
         }
-    }
+        let key = match &event {
+            Some(Event::Key(key)) if key.kind != KeyEventKind::Release => Some(key.code),
+            _ => None,
+        };
+        let screen = std::mem::replace(&mut self.screen, Screen::title(false));
+        self.screen = match screen {
+            Screen::Title(mut menu) => match key {
+                Some(_) if self.warning.take().is_some() => Screen::Title(menu),
+                Some(KeyCode::Esc | KeyCode::Char('q')) => return false,
+                Some(code) => match menu.handle_key(code) {
+                    Some(TitleChoice::Resume) => {
+                        match save::resume(&self.paths.saves, &self.username) {
+                            Ok(game_context) => {
+                                self.animations.reset();
+                                self.resumed = true;
+                                Screen::Game(game_context)
+                            }
+                            // NOTE: a save that cannot be resumed is moved away, drop the entry
+                            Err(e) => {
+                                self.warning = Some(format!("cannot resume: {}", e));
+                                self.title()
+                            }
+                        }
+                    }
+                    Some(TitleChoice::Play) => Screen::ModeSelect(ModeSelect::new(1)),
+                    Some(TitleChoice::Settings) => {
+                        Screen::Settings(Settings::new(self.config.keys.clone()))
+                    }
+                    Some(TitleChoice::Quit) => return false,
+                    None => Screen::Title(menu),
+                },
+                None => Screen::Title(menu),
+            },
+            Screen::ModeSelect(mut mode_select) => match key {
+                Some(KeyCode::Esc) => self.title(),
+                Some(code) => match mode_select.handle_key(code) {
+                    Some(ModeChoice::Mode(mode)) => {
+                        self.animations.reset();
+                        Screen::Game(GameContext::new(
+                            mode,
+                            mode_select.start_level,
+                            self.username.clone(),
+                            rand::random(),
+                        ))
+                    }
+                    Some(ModeChoice::Back) => self.title(),
+                    Some(ModeChoice::StartLevel) | None => Screen::ModeSelect(mode_select),
+                },
+                None => Screen::ModeSelect(mode_select),
+            },
+            Screen::Settings(mut settings) => match &event {
+                // esc leave the settings, unless it is the key being bound
+                Some(Event::Key(key))
+                    if key.code == KeyCode::Esc
+                        && key.kind == KeyEventKind::Press
+                        && !settings.is_listening() =>
+                {
+                    self.title()
+                }
+                Some(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    match settings.handle_key(*key) {
+                        Some(SettingsChoice::Save) => {
+                            self.save_keys(&mut settings);
+                            Screen::Settings(settings)
+                        }
+                        Some(SettingsChoice::Back) => self.title(),
+                        _ => Screen::Settings(settings),
+                    }
+                }
+                _ => Screen::Settings(settings),
+            },
+            Screen::Game(mut game_context) => {
+                match update(&mut game_context, &mut self.input, event) {
+                    GameEvent::Tick => Screen::Game(game_context),
+                    GameEvent::Restart => {
+                        self.end_game(&game_context);
+                        game_context.reset();
+                        self.animations.reset();
+                        Screen::Game(game_context)
+                    }
+                    GameEvent::GameOver => {
+                        let rank = self.end_game(&game_context);
+                        Screen::Results(Results::new(game_context, rank))
+                    }
+                    GameEvent::Quit => {
+                        self.end_game(&game_context);
+                        self.title()
+                    }
+                    GameEvent::Suspend => match save::suspend(&self.paths.saves, &game_context) {
+                        // the save now hold this game, it is not over
+                        Ok(()) => {
+                            self.resumed = false;
+                            self.title()
+                        }
+                        Err(e) => {
+                            // stay paused, the player can still play or quit
+                            if let Some(pause_menu) = game_context.pause.as_mut() {
+                                pause_menu.title = format!("cannot save: {}", e);
+                            }
+                            Screen::Game(game_context)
+                        }
+                    },
+                }
+            }
+            Screen::Results(mut results) => match key {
+                Some(KeyCode::Esc) => self.title(),
+                Some(code) => match results.menu.handle_key(code) {
+                    Some(ResultsChoice::PlayAgain) => {
+                        results.game.reset();
+                        self.animations.reset();
+                        Screen::Game(results.game)
+                    }
+                    Some(ResultsChoice::Menu) => self.title(),
+                    Some(ResultsChoice::Quit) => return false,
+                    None => Screen::Results(results),
+                },
+                None => Screen::Results(results),
+            },
+            Screen::Replay(mut replay_viewer) => match key {
+                Some(KeyCode::Esc | KeyCode::Char('q')) => return false,
+                _ => {
+                    replay_viewer.update(key);
+                    Screen::Replay(replay_viewer)
+                }
+            },
+        };
+
+        // turn what happened in the engine into animations
+        let game = match &mut self.screen {
+            Screen::Game(game_context) => Some(game_context),
+            Screen::Replay(replay_viewer) => Some(&mut replay_viewer.game),
+            _ => None,
+        };
+        if let Some(game) = game {
+            for engine_event in game.events.drain(..) {
+                self.animations.push(engine_event);
+            }
+            self.animations.tick();
+        }
 
-    // sideways collisions
-    if next_tetromino.collide(&game_context.grid) {
-        for x in WALL_KICK_OFFSETS {
-            let mut kicked_tetromino = next_tetromino.clone();
-            kicked_tetromino.pos.x += x;
-            if !kicked_tetromino.collide(&game_context.grid) {
-                // move
-                *game_context.bag.last_mut().expect("bag empty on move") = kicked_tetromino.clone();
-                return GameEvent::Tick; // skip gravity check for a tick
+        // NOTE: here and not in render, it can play replays and write the cache
+        let shown = match &self.screen {
+            Screen::Game(game) => Some((game.mode, game.username.clone())),
+            Screen::Replay(replay_viewer) => {
+                Some((replay_viewer.game.mode, replay_viewer.game.username.clone()))
             }
+            _ => None,
+        };
+        if let Some((mode, username)) = shown {
+            self.refresh_panel(mode, &username);
         }
-        return GameEvent::Tick; // skip graviry check for a tick
+        true
     }
-
-    // move down
-    let delay: Duration = get_delay_from_level(game_context.level);
-    if *time_since_last_move >= delay {
-        *time_since_last_move = Duration::ZERO;
-        // ground collision
-        if next_tetromino.try_move_down(&game_context.grid).is_err() {
-            return place_down(game_context, 1.0);
+    /// apply the edited bindings and write them to the config file
+    fn save_keys(&mut self, settings: &mut Settings) {
+        let mut config = self.config.clone();
+        config.keys = settings.keys.clone();
+        if let Err(e) = config.validate() {
+            settings.message = e;
+            return;
+        }
+        self.input.bindings = config.keys.clone();
+        self.config = config;
+        settings.message = match &self.config_path {
+            Some(path) => match self.config.save(path) {
+                Ok(()) => format!("saved to {}", path.display()),
+                Err(e) => format!("cannot save: {}", e),
+            },
+            None => "no config path, kept for this session".to_owned(),
+        };
+    }
+    fn title(&self) -> Screen {
+        Screen::title(save::exists(&self.paths.saves, &self.username))
+    }
+    /// save the run and its replay, return the rank it got
+    fn end_game(&mut self, game_context: &GameContext) -> Option<usize> {
+        // NOTE: losing a replay is not worth crashing the game
+        let replay = Replay::new(game_context);
+        let name = replay
+            .save(&self.paths.replays)
+            .ok()
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()));
+        if name.is_some() {
+            self.verifier.remember(&replay, game_context);
+        }
+        if std::mem::take(&mut self.resumed)
+            && let Err(e) = save::remove(&self.paths.saves, &game_context.username)
+        {
+            self.warning = Some(format!("cannot delete the save: {}", e));
+        }
+        let entry = Entry::new(game_context, name);
+        let rank = match entry.ranked().then(|| self.storage.rank(&entry)) {
+            Some(Ok(rank)) => Some(rank),
+            Some(Err(e)) => {
+                self.warning = Some(e.to_string());
+                None
+            }
+            None => None,
+        };
+        // NOTE: the run is still ranked when it cannot be saved
+        if let Err(e) = self.storage.add(entry) {
+            self.warning = Some(e.to_string());
+        }
+        self.panel = None;
+        rank
+    }
+    /// query the leaderboard for the game on screen, unless it is already there
+    fn refresh_panel(&mut self, mode: GameMode, username: &str) {
+        if let Some(panel) = &self.panel
+            && panel.mode == mode
+            && panel.username == username
+        {
+            return;
+        }
+        // NOTE: an empty panel is better than no game, the error show up on the title
+        let rows = self
+            .storage
+            .top(mode, RULESET, TOP, username)
+            .unwrap_or_else(|e| {
+                self.warning = Some(e.to_string());
+                Vec::new()
+            });
+        let rows = self.verifier.verify_rows(rows);
+        // NOTE: only a cache, the replays are played again without it
+        let _ = self.verifier.save();
+        self.panel = Some(Panel {
+            mode,
+            username: username.to_owned(),
+            rows,
+        });
+    }
+    fn render(&mut self, frame: &mut Frame) {
+        let area = frame.area();
+        if render::too_small(area.width, area.height) {
+            render::render_too_small(frame, &self.theme);
+            return;
+        }
+        let rows = self
+            .panel
+            .as_ref()
+            .map(|panel| panel.rows.as_slice())
+            .unwrap_or(&[]);
+        match &mut self.screen {
+            Screen::Title(menu) => render::render_menu(
+                frame,
+                &self.theme,
+                &menu.title,
+                &match &self.warning {
+                    Some(warning) => format!("{}\n\n{}\n\n{}", CREDITS, menu, warning),
+                    None => format!("{}\n\n{}", CREDITS, menu),
+                },
+            ),
+            Screen::ModeSelect(mode_select) => render::render_menu(
+                frame,
+                &self.theme,
+                &mode_select.menu.title,
+                &mode_select.menu.to_string(),
+            ),
+            Screen::Settings(settings) => render::render_menu(
+                frame,
+                &self.theme,
+                &settings.menu.title,
+                &settings.to_string(),
+            ),
+            Screen::Game(game_context) => render::render_game(
+                frame,
+                game_context,
+                rows,
+                &self.config.display,
+                &self.theme,
+                &self.animations,
+            ),
+            Screen::Results(results) => render::render_menu(
+                frame,
+                &self.theme,
+                &results.menu.title,
+                &results.to_string(),
+            ),
+            Screen::Replay(replay_viewer) => {
+                render::render_game(
+                    frame,
+                    &mut replay_viewer.game,
+                    rows,
+                    &self.config.display,
+                    &self.theme,
+                    &self.animations,
+                );
+                render::render_status(frame, &self.theme, &replay_viewer.to_string());
+            }
         }
     }
-
-    // move
-    *game_context.bag.last_mut().expect("bag empty on move") = next_tetromino.clone();
-    GameEvent::Tick
 }
 
-fn get_delay_from_level(level: u64) -> Duration {
-    // formula from https://tetris.wiki/Marathon
-    Duration::from_secs_f64((0.8 - ((level as f64 - 1.0) * 0.007)).powf(level as f64 - 1.0))
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "usage: {} [--config <file>] [--scores <file>] [replay <file or text> | export <file or text> | scores | history [user] | verify [user]]",
+        program
+    );
+    eprintln!(
+        "scores: --scores, ${}, leaderboard.path in the config, {} if it is set up, \
+         then the data dir ($XDG_DATA_HOME/tetris), where a {} from before is imported",
+        SCORES_ENV, SHARED_DIR, SCORES_PATH
+    );
+    std::process::exit(2);
 }
 
-fn place_down(game_context: &mut GameContext, score_multiplier: f32) -> GameEvent {
-    // place tetromino on grid
-    game_context
-        .bag
-        .pop()
-        .expect("bag empty on groud col")
-        .stamp_onto(&mut game_context.grid)
-        .expect("tetromino move de-sync");
-
-    // refill bag
-    if game_context.bag.is_empty() {
-        game_context.bag = new_bag();
+fn open_storage(backend: Backend, path: &Path) -> Result<Box<dyn Storage>, LeaderboardError> {
+    match backend {
+        Backend::Json => Ok(Box::new(JsonFile::open(path)?)),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Ok(Box::new(tetris::storage::Sqlite::open(path)?)),
+        // NOTE: refused by Config::validate
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => unreachable!("sqlite backend without the sqlite feature"),
     }
+}
 
-    // https://tetris.wiki/Scoring#Recent_guideline_compatible_games
-    let lines_cleared_this_frame = clear_lines(&mut game_context.grid);
-    match lines_cleared_this_frame {
-        0 => {}
-        1 => game_context.score += (100.0 * game_context.level as f32 * score_multiplier) as u64,
-        2 => game_context.score += (300.0 * game_context.level as f32 * score_multiplier) as u64,
-        3 => game_context.score += (500.0 * game_context.level as f32 * score_multiplier) as u64,
-        4 => game_context.score += (800.0 * game_context.level as f32 * score_multiplier) as u64,
-        _ => {} // TODO: error
-    }
-    // https://tetris.wiki/Marathon
-    game_context.total_lines_cleared += lines_cleared_this_frame as u64;
-    if game_context.total_lines_cleared / 10 > game_context.level {
-        game_context.level = game_context.total_lines_cleared / 10;
-    }
+fn exit_on_error<T>(result: Result<T, LeaderboardError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// copy the runs of an old scores file, it is left as it was
+fn import_legacy(
+    backend: Backend,
+    legacy: &Path,
+    scores: &Path,
+) -> Result<usize, LeaderboardError> {
+    let runs = LeaderBoard::load(legacy)?.entrys;
+    let count = runs.len();
+    open_storage(backend, scores)?.add_all(runs)?;
+    Ok(count)
+}
 
-    // check if the next tetromino will cause a game over
-    if game_context
-        .bag
-        .last()
-        .expect("bag empty")
-        .collide(&game_context.grid)
-    {
-        return GameEvent::GameOver;
+/// the leaderboard at `paths.scores`, the old `SCORES_PATH` is imported the first
+/// time it is opened
+/// NOTE: not before, `replay` and `export` have nothing to do with the scores
+fn open_leaderboard(
+    backend: Backend,
+    paths: &Paths,
+    warnings: &mut Vec<String>,
+) -> Result<Box<dyn Storage>, LeaderboardError> {
+    if let Some(legacy) = &paths.legacy {
+        warnings.push(match import_legacy(backend, legacy, &paths.scores) {
+            Ok(count) => format!(
+                "imported {} runs from {} into {}\nthe old file is kept, remove it when you want",
+                count,
+                legacy.display(),
+                paths.scores.display()
+            ),
+            Err(e) => format!("{}\ncannot import the old leaderboard", e),
+        });
     }
-    GameEvent::Tick
+    open_storage(backend, &paths.scores)
 }
 
-fn hard_drop(next_tetromino: &mut Tetromino, game_context: &mut GameContext) -> GameEvent {
-    while next_tetromino.try_move_down(&game_context.grid).is_ok() {}
-    *game_context.bag.last_mut().expect("bag empty on move") = next_tetromino.clone();
-    place_down(game_context, 1.0)
+/// for the commands, they print the warnings right away, the game show them on the title
+fn open_or_exit(backend: Backend, paths: &Paths, warnings: &mut Vec<String>) -> Box<dyn Storage> {
+    let storage = open_leaderboard(backend, paths, warnings);
+    for warning in warnings.drain(..) {
+        eprintln!("{}", warning);
+    }
+    exit_on_error(storage)
 }
 
-fn render(game_context: &GameContext, terminal: &mut DefaultTerminal) -> () {
-    let area = terminal.get_frame().area();
-    let cell_height = area.height / GRID_HEIGHT as u16;
-    let cell_width = cell_height * 2;
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut config_path: Option<PathBuf> = None;
+    let mut scores_path: Option<PathBuf> = None;
+    let mut positional: Vec<&str> = Vec::new();
+    let mut args_iter = args.iter().skip(1);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--config" => match args_iter.next() {
+                Some(path) => config_path = Some(PathBuf::from(path)),
+                None => usage(&args[0]),
+            },
+            "--scores" => match args_iter.next() {
+                Some(path) => scores_path = Some(PathBuf::from(path)),
+                None => usage(&args[0]),
+            },
+            _ => positional.push(arg),
+        }
+    }
 
-    let vertical_rect = Rect {
-        x: 0,
-        y: 0,
-        width: area.width,
-        // + 2 offset to avoid overlapping the borders (each sides)
-        height: cell_height * GRID_HEIGHT as u16 + 2,
+    // an explicit --config must exist, the default one is optional
+    let explicit = config_path.is_some();
+    let config_path = config_path.or_else(config::default_path);
+    let config = match &config_path {
+        Some(path) => Config::load(path, explicit).unwrap_or_else(|e| {
+            eprintln!("config error: {}", e);
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    // user themes live next to the config
+    let themes_dir = config_path
+        .as_deref()
+        .and_then(Path::parent)
+        .map(|dir| dir.join("themes"));
+    let mut theme = Theme::load(&config.display.theme, themes_dir.as_deref()).unwrap_or_else(|e| {
+        eprintln!("theme error: {}", e);
+        std::process::exit(1);
+    });
+    theme.adjust(&config.display);
+    theme.downmap(config.display.colors.unwrap_or_else(ColorSupport::detect));
+    let mut warnings = Vec::new();
+    let paths = Paths::resolve(scores_path, &config, &mut warnings);
+    let backend = config.leaderboard.backend;
+
+    let username = get_current_username()
+        .unwrap_or("User not found".into())
+        .into_string()
+        .expect("error converting OsString to String");
+    let screen = match positional.as_slice() {
+        [command @ ("replay" | "export"), source] => {
+            let replay = match Replay::load(source) {
+                Ok(replay) => replay,
+                Err(e) => {
+                    eprintln!("{}: {}", source, e);
+                    std::process::exit(1);
+                }
+            };
+            if *command == "export" {
+                println!("{}", replay.to_text());
+                return;
+            }
+            Screen::Replay(ReplayViewer::new(replay))
+        }
+        ["scores"] => {
+            // the old rulesets too, they are not shown in the game
+            let storage = open_or_exit(backend, &paths, &mut warnings);
+            let mut verifier = Verifier::new(&paths.replays, paths.verified.as_deref());
+            for (mode, ruleset) in exit_on_error(storage.tables()) {
+                let rows = exit_on_error(storage.top(mode, ruleset, usize::MAX, ""));
+                let rows = verifier.verify_rows(rows);
+                println!("{} (ruleset {})", mode.name(), ruleset);
+                println!("{}", format_rows(&rows, usize::MAX));
+            }
+            let _ = verifier.save();
+            return;
+        }
+        ["history", user @ ..] if user.len() <= 1 => {
+            let user = user.first().copied().unwrap_or(&username);
+            let storage = open_or_exit(backend, &paths, &mut warnings);
+            print!("{}", format_history(&exit_on_error(storage.history(user))));
+            return;
+        }
+        ["verify", user @ ..] if user.len() <= 1 => {
+            // the tables, or every ranked run of a player
+            let storage = open_or_exit(backend, &paths, &mut warnings);
+            let runs: Vec<Entry> = match user.first() {
+                Some(user) => exit_on_error(storage.history(user)),
+                None => exit_on_error(storage.tables())
+                    .into_iter()
+                    .flat_map(|(mode, ruleset)| {
+                        exit_on_error(storage.top(mode, ruleset, usize::MAX, ""))
+                    })
+                    .map(|(_, entry)| entry)
+                    .collect(),
+            };
+            // NOTE: without the cache, every replay is played again
+            let mut verifier = Verifier::new(&paths.replays, None);
+            let mut failed = false;
+            for entry in runs.iter().filter(|entry| entry.ranked()) {
+                let verification = verifier.verify(entry);
+                failed |= verification != Verification::Verified;
+                println!(
+                    "{} {: <8} {: <10} {: >8}  {}",
+                    format_date(entry.date),
+                    entry.mode.name(),
+                    entry.username,
+                    entry.value(),
+                    verification
+                );
+            }
+            std::process::exit(failed as i32);
+        }
+        [] => Screen::title(save::exists(&paths.saves, &username)),
+        _ => usage(&args[0]),
     };
 
-    let layout = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Fill(1),
-            // + 2 offset to avoid overlapping the borders (each sides)
-            Constraint::Length(cell_width * GRID_WIDTH as u16 + 2),
-            Constraint::Fill(1),
-        ])
-        .split(vertical_rect);
-
-    let left_panel = Paragraph::new(
-        CREDITS.to_owned()
-            + &format!(
-                "\nScore: {}\nlevel: {}",
-                game_context.score, game_context.level
-            ),
-    )
-    .style(Style::default().fg(Color::White))
-    .block(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_set(symbols::border::ROUNDED)
-            .border_style(Style::default().fg(ratatui::style::Color::DarkGray))
-            .title(" Tetris ")
-            .title_alignment(Alignment::Center)
-            .title_style(Style::default().fg(Color::White)),
-    );
-
-    let right_panel = Paragraph::new(game_context.leaderboard.to_string()).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_set(symbols::border::ROUNDED)
-            .border_style(Style::default().fg(ratatui::style::Color::DarkGray))
-            .title(" 42 lyon leaderboard ")
-            .title_alignment(Alignment::Center)
-            .title_style(Style::default().fg(Color::White)),
-    );
-
-    let playfield = Block::default()
-        .borders(Borders::ALL)
-        .border_set(symbols::border::ROUNDED)
-        .border_style(Style::default().fg(ratatui::style::Color::DarkGray))
-        .title(" Playfield ")
-        .title_alignment(Alignment::Center)
-        .title_style(Style::default().fg(Color::White));
+    let mut terminal = ratatui::init();
+    // pause on focus lost, ignored by terminals that dont report focus
+    let _ = crossterm::execute!(std::io::stdout(), EnableFocusChange);
+    // key releases let us do DAS / ARR, otherwise rely on the terminal key repeat
+    let release_events = crossterm::terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if release_events {
+        let _ = crossterm::execute!(
+            std::io::stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        );
+    }
 
-    // create a new temp grid that hold the current tetromino
-    let mut grid_with_tetromino = game_context.grid.clone();
-    game_context
-        .bag
-        .last()
-        .expect("bag empty in rendering")
-        .stamp_onto(&mut grid_with_tetromino)
-        .expect("collision cauth in render, sould've been cauth in update");
+    if !release_events && config.handling != Handling::default() {
+        warnings.push(
+            "handling: this terminal does not report key releases\n\
+             das, arr and sdf are ignored, held keys use the terminal key repeat"
+                .to_owned(),
+        );
+    }
 
-    terminal
-        .draw(|frame| {
-            frame.render_widget(left_panel, layout[0]);
-            frame.render_widget(playfield, layout[1]);
-            frame.render_widget(right_panel, layout[2]);
+    // NOTE: dont crash for the scores, play with an empty leaderboard
+    let storage = match open_leaderboard(backend, &paths, &mut warnings) {
+        Ok(storage) => storage,
+        Err(e) if backend == Backend::Json => {
+            warnings.push(e.to_string());
+            Box::new(JsonFile::empty(&paths.scores))
+        }
+        // NOTE: nothing to fall back on, a database we cannot open would not take the runs
+        Err(e) => {
+            ratatui::restore();
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-            for (i, line) in grid_with_tetromino.iter().enumerate() {
-                for (j, cell) in line.iter().enumerate() {
-                    // + 1 offset to avoid overlapping the border
-                    let y = layout[1].y + 1 + (i as u16) * cell_height;
-                    let x = layout[1].x + 1 + (j as u16) * cell_width;
+    let mut app = App {
+        screen,
+        username,
+        storage,
+        panel: None,
+        verifier: Verifier::new(&paths.replays, paths.verified.as_deref()),
+        input: Input::new(&config, release_events),
+        animations: Animations::new(config.display.reduced_motion),
+        config,
+        config_path,
+        theme,
+        paths,
+        size: crossterm::terminal::size().unwrap_or((0, 0)),
+        warning: (!warnings.is_empty()).then(|| warnings.join("\n\n")),
+        resumed: false,
+    };
 
-                    let cell_rect = Rect {
-                        x: x,
-                        y: y,
-                        width: cell_width,
-                        height: cell_height,
-                    };
+    let tick_rate: Duration = Duration::from_secs(1) / TARGET_FPS;
+    let mut next_frame = Instant::now();
+    let mut events = VecDeque::new();
 
-                    let block = Block::default()
-                        .borders(Borders::NONE)
-                        .style(if let Some(color) = cell {
-                            Style::default().fg(*color).bg(*color)
-                        } else {
-                            Style::default()
-                        })
-                        .title(if cell.is_some() { "" } else { "." }); // '█'
-                    frame.render_widget(block, cell_rect);
-                }
+    'gameloop: loop {
+        // fixed timestep, the engine count frames and not seconds
+        let now = Instant::now();
+        if now < next_frame {
+            std::thread::sleep(next_frame - now);
+        }
+        // NOTE: dont catch up after a stall (slow terminal, ctrl+z), just slow down
+        next_frame = next_frame.max(now) + tick_rate;
+
+        // NOTE: read every waiting event, one a frame would lag behind the key repeat
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            match event::read() {
+                Ok(event) => queue_event(&mut events, event),
+                Err(_) => break,
             }
-        })
-        .expect("ratatui rendering error");
+        }
+        if !app.update(events.pop_front()) {
+            break 'gameloop;
+        }
+        terminal
+            .draw(|frame| app.render(frame))
+            .expect("ratatui rendering error");
+    }
+    if release_events {
+        let _ = crossterm::execute!(std::io::stdout(), PopKeyboardEnhancementFlags);
+    }
+    let _ = crossterm::execute!(std::io::stdout(), DisableFocusChange);
+    ratatui::restore();
+    // the last games may not have been in a panel yet
+    let _ = app.verifier.save();
+    // the alternate screen is gone, it stay readable this time
+    if let Some(warning) = app.warning {
+        eprintln!("{}", warning);
+    }
 }
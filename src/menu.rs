@@ -0,0 +1,52 @@
+use crossterm::event::KeyCode;
+
+/// vertical list of choices, navigated with arrows or vim keys
+#[derive(Debug, Clone)]
+pub struct Menu<T> {
+    pub title: String,
+    items: Vec<(T, String)>,
+    selected: usize,
+}
+
+impl<T: Copy> Menu<T> {
+    pub fn new(title: &str, items: Vec<(T, String)>) -> Self {
+        Menu {
+            title: title.to_owned(),
+            items,
+            selected: 0,
+        }
+    }
+    /// return the selected choice on enter
+    pub fn handle_key(&mut self, code: KeyCode) -> Option<T> {
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+                None
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1) % self.items.len();
+                None
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => Some(self.selected()),
+            _ => None,
+        }
+    }
+    pub fn selected(&self) -> T {
+        self.items[self.selected].0
+    }
+    pub fn set_label(&mut self, choice: usize, label: String) {
+        self.items[choice].1 = label;
+    }
+}
+
+impl<T> std::fmt::Display for Menu<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // pad the labels so the centered lines stay aligned
+        let width = self.items.iter().map(|(_, label)| label.len()).max();
+        for (i, (_, label)) in self.items.iter().enumerate() {
+            let cursor = if i == self.selected { ">" } else { " " };
+            writeln!(f, "{} {: <2$}", cursor, label, width.unwrap_or(0))?;
+        }
+        Ok(())
+    }
+}
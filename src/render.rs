@@ -0,0 +1,273 @@
+use ratatui::{
+    Frame,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Padding, Paragraph, Wrap},
+};
+
+use crate::CREDITS;
+use crate::animation::Animations;
+use crate::board::BoardMode;
+use crate::config::DisplayConfig;
+use crate::game::{GameContext, GameMode, SPRINT_LINES};
+use crate::leaderboard::{Entry, Ranking, TOP, Verification, format_rows};
+use crate::tetromino::Tetromino;
+use crate::theme::Theme;
+use crate::widget::TetrisWidget;
+
+/// the bordered box everything is drawn in
+pub fn panel<'a>(theme: &Theme, title: &'a str) -> Block<'a> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_set(theme.border.set())
+        .border_style(Style::default().fg(theme.border_color))
+        .title(title)
+        .title_alignment(Alignment::Center)
+        .title_style(Style::default().fg(theme.text_color))
+}
+
+/// bordered box in the middle of the screen, for menus and summaries
+pub fn render_menu(frame: &mut Frame, theme: &Theme, title: &str, text: &str) {
+    let width = text.lines().map(|line| line.len()).max().unwrap_or(0) as u16 + 8;
+    let height = text.lines().count() as u16 + 4;
+    let area = frame.area();
+    let rect = Rect {
+        x: area.x + area.width.saturating_sub(width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width: width.min(area.width),
+        height: height.min(area.height),
+    };
+    let title = format!(" {} ", title);
+    let menu = Paragraph::new(text.to_owned())
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(theme.text_color))
+        .block(panel(theme, &title).padding(Padding::vertical(1)));
+    frame.render_widget(Clear, rect);
+    frame.render_widget(menu, rect);
+}
+
+/// one line at the bottom of the screen
+pub fn render_status(frame: &mut Frame, theme: &Theme, text: &str) {
+    let area = frame.area();
+    let rect = Rect {
+        x: area.x,
+        y: area.y + area.height.saturating_sub(1),
+        width: area.width,
+        height: area.height.min(1),
+    };
+    let status = Paragraph::new(text.to_owned()).style(
+        Style::default()
+            .fg(theme.text_color)
+            .add_modifier(Modifier::REVERSED),
+    );
+    frame.render_widget(Clear, rect);
+    frame.render_widget(status, rect);
+}
+
+/// a tetromino in its spawn rotation, as two lines of text
+pub fn preview_lines(tetromino: &Tetromino, theme: &Theme) -> [Line<'static>; 2] {
+    [0, 1].map(|y| {
+        Line::from(
+            (-1..=2)
+                .map(|x| {
+                    if tetromino.blocks.iter().any(|b| b.x == x && b.y == y) {
+                        let (text, style) = theme.cell(tetromino.kind, 2, true);
+                        Span::styled(text, style)
+                    } else {
+                        Span::raw("  ")
+                    }
+                })
+                .collect::<Vec<_>>(),
+        )
+    })
+}
+
+/// below that the game is not playable, see `render_too_small`
+pub const MIN_WIDTH: u16 = 24;
+pub const MIN_HEIGHT: u16 = 8;
+/// narrowest a side panel is worth showing
+const PANEL_WIDTH: u16 = 24;
+
+pub fn too_small(width: u16, height: u16) -> bool {
+    width < MIN_WIDTH || height < MIN_HEIGHT
+}
+
+pub fn render_too_small(frame: &mut Frame, theme: &Theme) {
+    let area = frame.area();
+    let text = format!("terminal too small\n(need {}×{})", MIN_WIDTH, MIN_HEIGHT);
+    let height = text.lines().count() as u16;
+    let rect = Rect {
+        y: area.y + area.height.saturating_sub(height) / 2,
+        height: height.min(area.height),
+        ..area
+    };
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+            .style(Style::default().fg(theme.text_color)),
+        rect,
+    );
+}
+
+/// where the side panels go, from wide to narrow terminals
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Panels {
+    /// stats on the left, leaderboard on the right
+    Sides,
+    /// both on the right of the board, one above the other
+    Stacked,
+    /// no panels, a status line under the board
+    Collapsed,
+}
+
+pub fn render_game(
+    frame: &mut Frame,
+    game_context: &mut GameContext,
+    // the top of the leaderboard for this mode, see Storage::top and Verifier::verify_rows
+    rows: &[(usize, Entry, Verification)],
+    display: &DisplayConfig,
+    theme: &Theme,
+    animations: &Animations,
+) {
+    let area = frame.area();
+    // the board get as big as the height allow, the panels take what is left
+    // - 2 for the borders (each sides)
+    let mut board_mode =
+        BoardMode::fit(area.width.saturating_sub(2), area.height.saturating_sub(2));
+    let side_width = area
+        .width
+        .saturating_sub(board_mode.map(|mode| mode.size().0).unwrap_or(0) + 2);
+    let panels = if side_width >= PANEL_WIDTH * 2 {
+        Panels::Sides
+    } else if side_width >= PANEL_WIDTH {
+        Panels::Stacked
+    } else {
+        Panels::Collapsed
+    };
+    if panels == Panels::Collapsed {
+        // one more line for the status
+        board_mode = BoardMode::fit(area.width.saturating_sub(2), area.height.saturating_sub(3));
+    }
+    let (board_width, board_height) = board_mode.map(|mode| mode.size()).unwrap_or((0, 0));
+
+    let vertical_rect = Rect {
+        x: 0,
+        y: 0,
+        width: area.width,
+        // + 2 offset to avoid overlapping the borders (each sides)
+        height: (board_height + 2).min(area.height),
+    };
+
+    // [left panel, board, right panel], the panels can be empty
+    let layout: [Rect; 3] = match panels {
+        Panels::Sides | Panels::Collapsed => Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Fill(1),
+                // + 2 offset to avoid overlapping the borders (each sides)
+                Constraint::Length(board_width + 2),
+                Constraint::Fill(1),
+            ])
+            .areas(vertical_rect),
+        Panels::Stacked => {
+            let [board, side] = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(board_width + 2), Constraint::Fill(1)])
+                .areas(vertical_rect);
+            let [top, bottom] = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Fill(2), Constraint::Fill(1)])
+                .areas(side);
+            [top, board, bottom]
+        }
+    };
+
+    let mut left_text = CREDITS.to_owned()
+        + &format!(
+            "\nScore: {}\nlevel: {}\ntime: {:02}:{:02}",
+            game_context.score,
+            game_context.level,
+            game_context.play_time.as_secs() / 60,
+            game_context.play_time.as_secs() % 60
+        );
+    left_text += &format!("\n\n{} mode", game_context.mode.name());
+    if game_context.mode == GameMode::Sprint {
+        left_text += &format!(
+            "\nlines: {}/{}",
+            game_context.total_lines_cleared, SPRINT_LINES
+        );
+    }
+    if game_context.mode == GameMode::Zen {
+        left_text += &format!(
+            "\nu: undo ({})\nctrl+r: redo ({})",
+            game_context.history.undo_len(),
+            game_context.history.redo_len()
+        );
+    }
+    let mut left_lines: Vec<Line> = left_text.lines().map(Line::from).collect();
+    if display.preview > 0 {
+        left_lines.push(Line::from(""));
+        left_lines.push(Line::from("next:"));
+        // the next ones are right before the current one
+        for tetromino in game_context.bag.iter().rev().skip(1).take(display.preview) {
+            left_lines.extend(preview_lines(tetromino, theme));
+            left_lines.push(Line::from(""));
+        }
+    }
+    let left_panel = Paragraph::new(left_lines)
+        .style(Style::default().fg(theme.text_color))
+        .block(panel(theme, " Tetris "));
+
+    let right_text = match Ranking::of(game_context.mode) {
+        Some(_) => format!("{}\n\n{}", game_context.mode.name(), format_rows(rows, TOP)),
+        None => format!("no leaderboard in {}", game_context.mode.name()),
+    };
+    let right_panel = Paragraph::new(right_text)
+        .style(Style::default().fg(theme.text_color))
+        .block(panel(theme, " 42 lyon leaderboard "));
+
+    if panels == Panels::Collapsed {
+        let status = format!(
+            "{} lvl {} {:02}:{:02}",
+            game_context.score,
+            game_context.level,
+            game_context.play_time.as_secs() / 60,
+            game_context.play_time.as_secs() % 60
+        );
+        let status_rect = Rect {
+            y: layout[1].bottom(),
+            height: 1,
+            ..area
+        }
+        .intersection(area);
+        frame.render_widget(
+            Paragraph::new(status)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(theme.text_color)),
+            status_rect,
+        );
+    } else {
+        frame.render_widget(left_panel, layout[0]);
+        frame.render_widget(right_panel, layout[2]);
+    }
+
+    let playfield = TetrisWidget::new(theme)
+        .ghost(display.ghost)
+        .animations(animations)
+        .block(panel(theme, " Playfield "));
+    frame.render_stateful_widget(playfield, layout[1], game_context);
+
+    // too narrow with the dense modes, use a popup
+    if let Some(pause_menu) = &game_context.pause {
+        let text = format!("{}\n\n{}", pause_menu.title, pause_menu);
+        if text
+            .lines()
+            .any(|line| line.len() + 2 > layout[1].width as usize)
+        {
+            render_menu(frame, theme, "PAUSED", &pause_menu.to_string());
+        }
+    }
+}
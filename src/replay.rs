@@ -0,0 +1,597 @@
+//! Replay file format, all integers little endian, "varint" is unsigned LEB128
+//!
+//! ```text
+//! magic        4 bytes  "TTRP"
+//! version      u8       FORMAT_VERSION
+//! ruleset      u16      game::RULESET the game was played with
+//! mode         u8       0 marathon, 1 zen, 2 sprint
+//! start level  u8
+//! seed         u64
+//! date         u64      unix time, seconds
+//! username     u8 length, then utf-8 bytes
+//! frames       varint   length of the game, in engine frames
+//! score        varint   final score
+//! final hash   u64      fnv-1a of the final score, level, lines and frames
+//! inputs       varint   number of inputs, then for each input:
+//!   delta      varint   frames since the previous input (since frame 0 for the first)
+//!   action     u8       0 left, 1 right, 2 rotate, 3 hard drop, 4 undo, 5 redo,
+//!                       6 soft drop
+//! ```
+//!
+//! The text encoding is `TEXT_PREFIX` followed by the file in url safe base64,
+//! short enough to be pasted in a chat message.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use crossterm::event::KeyCode;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::game::{Action, GameContext, GameEvent, GameMode, RULESET, step};
+
+const MAX_SPEED: f64 = 8.0;
+const MIN_SPEED: f64 = 0.25;
+const MAGIC: &[u8; 4] = b"TTRP";
+const FORMAT_VERSION: u8 = 1;
+pub const TEXT_PREFIX: &str = "tetris-replay:";
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    NotAReplay,
+    UnsupportedFormat(u8),
+    IncompatibleRuleset(u16),
+    Corrupted(&'static str),
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "cannot read replay: {}", e),
+            ReplayError::NotAReplay => write!(f, "not a tetris replay"),
+            ReplayError::UnsupportedFormat(version) => write!(
+                f,
+                "replay format version {} is not supported (this tetris read version {})",
+                version, FORMAT_VERSION
+            ),
+            ReplayError::IncompatibleRuleset(ruleset) => write!(
+                f,
+                "replay was played with ruleset {}, this tetris plays ruleset {}: it would desync",
+                ruleset, RULESET
+            ),
+            ReplayError::Corrupted(what) => write!(f, "corrupted replay: {}", what),
+        }
+    }
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// a game is its seed plus every input, the engine do the rest
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub ruleset: u16,
+    pub username: String,
+    pub mode: GameMode,
+    pub start_level: u64,
+    pub seed: u64,
+    /// unix time, seconds
+    pub date: u64,
+    /// length of the game, in engine frames
+    pub frames: u64,
+    pub score: u64,
+    /// `final_hash` of the game at the end, to catch desyncs
+    pub final_hash: u64,
+    pub inputs: Vec<(u64, Action)>,
+}
+
+/// fnv-1a of what the game ended with
+pub fn final_hash(context: &GameContext) -> u64 {
+    let bytes: Vec<u8> = [
+        context.score,
+        context.level,
+        context.total_lines_cleared,
+        context.frame,
+    ]
+    .iter()
+    .flat_map(|value| value.to_le_bytes())
+    .collect();
+    fnv1a(&bytes)
+}
+
+/// 64 bit fnv-1a, unlike the std hasher it is the same from one build to the next
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn mode_to_byte(mode: GameMode) -> u8 {
+    match mode {
+        GameMode::Marathon => 0,
+        GameMode::Zen => 1,
+        GameMode::Sprint => 2,
+    }
+}
+
+fn mode_from_byte(byte: u8) -> Result<GameMode, ReplayError> {
+    match byte {
+        0 => Ok(GameMode::Marathon),
+        1 => Ok(GameMode::Zen),
+        2 => Ok(GameMode::Sprint),
+        _ => Err(ReplayError::Corrupted("unknown mode")),
+    }
+}
+
+fn action_to_byte(action: Action) -> u8 {
+    match action {
+        Action::Left => 0,
+        Action::Right => 1,
+        Action::Rotate => 2,
+        Action::HardDrop => 3,
+        Action::Undo => 4,
+        Action::Redo => 5,
+        Action::SoftDrop => 6,
+    }
+}
+
+fn action_from_byte(byte: u8) -> Result<Action, ReplayError> {
+    match byte {
+        0 => Ok(Action::Left),
+        1 => Ok(Action::Right),
+        2 => Ok(Action::Rotate),
+        3 => Ok(Action::HardDrop),
+        4 => Ok(Action::Undo),
+        5 => Ok(Action::Redo),
+        6 => Ok(Action::SoftDrop),
+        _ => Err(ReplayError::Corrupted("unknown action")),
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// read the bytes of a replay, front to back
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], ReplayError> {
+        if self.bytes.len() < len {
+            return Err(ReplayError::Corrupted("unexpected end of file"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, ReplayError> {
+        Ok(u16::from_le_bytes(
+            self.take(2)?.try_into().expect("took 2 bytes"),
+        ))
+    }
+    fn u64(&mut self) -> Result<u64, ReplayError> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("took 8 bytes"),
+        ))
+    }
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReplayError::Corrupted("varint too long"))
+    }
+}
+
+impl Replay {
+    pub fn new(context: &GameContext) -> Self {
+        Replay {
+            ruleset: RULESET,
+            username: context.username.clone(),
+            mode: context.mode,
+            start_level: context.start_level,
+            seed: context.seed,
+            date: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            frames: context.frame,
+            score: context.score,
+            final_hash: final_hash(context),
+            inputs: context.inputs.clone(),
+        }
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.extend(self.ruleset.to_le_bytes());
+        bytes.push(mode_to_byte(self.mode));
+        bytes.push(self.start_level as u8);
+        bytes.extend(self.seed.to_le_bytes());
+        bytes.extend(self.date.to_le_bytes());
+        // NOTE: usernames are short, cut at 255 bytes anyway
+        let mut username_len = self.username.len().min(u8::MAX as usize);
+        while !self.username.is_char_boundary(username_len) {
+            username_len -= 1;
+        }
+        bytes.push(username_len as u8);
+        bytes.extend(&self.username.as_bytes()[..username_len]);
+        write_varint(&mut bytes, self.frames);
+        write_varint(&mut bytes, self.score);
+        bytes.extend(self.final_hash.to_le_bytes());
+        write_varint(&mut bytes, self.inputs.len() as u64);
+        let mut previous_frame = 0;
+        for (frame, action) in self.inputs.iter() {
+            write_varint(&mut bytes, frame - previous_frame);
+            bytes.push(action_to_byte(*action));
+            previous_frame = *frame;
+        }
+        bytes
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = reader.u8()?;
+        if version != FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedFormat(version));
+        }
+        let ruleset = reader.u16()?;
+        if ruleset != RULESET {
+            return Err(ReplayError::IncompatibleRuleset(ruleset));
+        }
+        let mode = mode_from_byte(reader.u8()?)?;
+        let start_level = reader.u8()? as u64;
+        let seed = reader.u64()?;
+        let date = reader.u64()?;
+        let username_len = reader.u8()? as usize;
+        let username = String::from_utf8(reader.take(username_len)?.to_vec())
+            .map_err(|_| ReplayError::Corrupted("username is not utf-8"))?;
+        let frames = reader.varint()?;
+        let score = reader.varint()?;
+        let final_hash = reader.u64()?;
+        let input_count = reader.varint()?;
+        let mut inputs = Vec::new();
+        let mut frame: u64 = 0;
+        for _ in 0..input_count {
+            frame = frame
+                .checked_add(reader.varint()?)
+                .ok_or(ReplayError::Corrupted("frame overflow"))?;
+            inputs.push((frame, action_from_byte(reader.u8()?)?));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(ReplayError::Corrupted("trailing bytes"));
+        }
+        Ok(Replay {
+            ruleset,
+            username,
+            mode,
+            start_level,
+            seed,
+            date,
+            frames,
+            score,
+            final_hash,
+            inputs,
+        })
+    }
+    /// play the whole game again, the context is how it ended
+    pub fn simulate(&self) -> GameContext {
+        let mut viewer = ReplayViewer::new(self.clone());
+        while !viewer.finished() {
+            viewer.step_frame();
+        }
+        viewer.game
+    }
+    /// text encoding, to share a replay in a chat message
+    pub fn to_text(&self) -> String {
+        TEXT_PREFIX.to_owned() + &URL_SAFE_NO_PAD.encode(self.to_bytes())
+    }
+    pub fn from_text(text: &str) -> Result<Self, ReplayError> {
+        let encoded = text
+            .trim()
+            .strip_prefix(TEXT_PREFIX)
+            .ok_or(ReplayError::NotAReplay)?;
+        let bytes = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|_| ReplayError::Corrupted("invalid base64"))?;
+        Replay::from_bytes(&bytes)
+    }
+    /// load a binary replay file, a text encoded one, or the text itself
+    pub fn load(source: &str) -> Result<Self, ReplayError> {
+        if source.trim().starts_with(TEXT_PREFIX) {
+            return Replay::from_text(source);
+        }
+        Replay::parse(&std::fs::read(source)?)
+    }
+    /// what a replay file hold, binary or text encoded
+    pub fn parse(bytes: &[u8]) -> Result<Self, ReplayError> {
+        if bytes.starts_with(TEXT_PREFIX.as_bytes()) {
+            let text = std::str::from_utf8(bytes).map_err(|_| ReplayError::NotAReplay)?;
+            return Replay::from_text(text);
+        }
+        Replay::from_bytes(bytes)
+    }
+    /// write to `<dir>/<username>-<date>.ttr`, return the path
+    /// NOTE: `-1`, `-2`... after the date for the games that end in the same second
+    pub fn save(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        for n in 0.. {
+            let name = match n {
+                0 => format!("{}-{}.ttr", self.username, self.date),
+                _ => format!("{}-{}-{}.ttr", self.username, self.date, n),
+            };
+            let path = dir.join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(&self.to_bytes())?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+        unreachable!("ran out of replay names")
+    }
+}
+
+/// re-simulate a replay, with pause, speed and frame by frame
+pub struct ReplayViewer {
+    pub replay: Replay,
+    pub game: GameContext,
+    /// next input to feed
+    cursor: usize,
+    pub paused: bool,
+    pub speed: f64,
+    /// frames owed to the engine, for speeds that are not whole
+    frame_debt: f64,
+}
+
+impl ReplayViewer {
+    pub fn new(replay: Replay) -> Self {
+        let game = GameContext::new(
+            replay.mode,
+            replay.start_level,
+            replay.username.clone(),
+            replay.seed,
+        );
+        ReplayViewer {
+            replay,
+            game,
+            cursor: 0,
+            paused: false,
+            speed: 1.0,
+            frame_debt: 0.0,
+        }
+    }
+    pub fn finished(&self) -> bool {
+        self.game.frame >= self.replay.frames
+    }
+    fn step_frame(&mut self) {
+        if self.finished() {
+            return;
+        }
+        let action = match self.replay.inputs.get(self.cursor) {
+            Some((frame, action)) if *frame == self.game.frame => {
+                self.cursor += 1;
+                Some(*action)
+            }
+            _ => None,
+        };
+        if let GameEvent::GameOver = step(&mut self.game, action) {
+            // the recording end here anyway, dont run past it
+            self.replay.frames = self.game.frame;
+        }
+    }
+    /// called once per frame of the viewer
+    pub fn update(&mut self, key: Option<KeyCode>) {
+        match key {
+            Some(KeyCode::Char(' ')) => self.paused = !self.paused,
+            Some(KeyCode::Up | KeyCode::Char('k') | KeyCode::Char('+')) => {
+                self.speed = (self.speed * 2.0).min(MAX_SPEED)
+            }
+            Some(KeyCode::Down | KeyCode::Char('j') | KeyCode::Char('-')) => {
+                self.speed = (self.speed / 2.0).max(MIN_SPEED)
+            }
+            Some(KeyCode::Right | KeyCode::Char('l') | KeyCode::Char('.')) => {
+                self.paused = true;
+                self.step_frame();
+            }
+            _ => {}
+        }
+        if self.paused {
+            return;
+        }
+        self.frame_debt += self.speed;
+        while self.frame_debt >= 1.0 {
+            self.frame_debt -= 1.0;
+            self.step_frame();
+        }
+    }
+}
+
+impl std::fmt::Display for ReplayViewer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = if self.finished() && final_hash(&self.game) == self.replay.final_hash {
+            "finished, verified"
+        } else if self.finished() {
+            "finished, DESYNC"
+        } else if self.paused {
+            "paused"
+        } else {
+            "playing"
+        };
+        write!(
+            f,
+            " REPLAY {} x{} frame {}/{} | space: play/pause  +/-: speed  .: step  esc: quit ",
+            state, self.speed, self.game.frame, self.replay.frames
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{GRID_HEIGHT, GRID_WIDTH, clear_lines};
+
+    /// the actions that put the current piece where it leave the flattest stack
+    fn plan(game: &GameContext) -> Vec<Action> {
+        let current = game.bag.last().expect("bag empty");
+        let mut best: Option<(i64, Vec<Action>)> = None;
+        for rotations in 0..4 {
+            for shift in -5i8..=5 {
+                let mut piece = current.clone();
+                for _ in 0..rotations {
+                    piece.rotate();
+                }
+                piece.pos.x += shift;
+                if piece.collide(&game.grid) {
+                    continue;
+                }
+                while piece.try_move_down(&game.grid) {}
+                let mut grid = game.grid;
+                for block in piece.blocks.iter() {
+                    let (x, y) = (piece.pos.x + block.x, piece.pos.y + block.y);
+                    grid[y as usize][x as usize] = Some(piece.kind);
+                }
+                let lines = clear_lines(&mut grid).len() as i64;
+                // the height and the holes under the top of each column
+                let columns = (0..GRID_WIDTH).map(|x| {
+                    let column: Vec<bool> = grid.iter().map(|row| row[x].is_some()).collect();
+                    let top = column
+                        .iter()
+                        .position(|filled| *filled)
+                        .unwrap_or(GRID_HEIGHT);
+                    let holes = column[top..].iter().filter(|filled| !**filled).count();
+                    ((GRID_HEIGHT - top) as i64, holes as i64)
+                });
+                let (height, holes) = columns.fold((0, 0), |(a, b), (c, d)| (a + c, b + d));
+                let score = lines * 8 - holes * 6 - height;
+                if best.as_ref().is_none_or(|(best, _)| score > *best) {
+                    let mut actions = vec![Action::Rotate; rotations];
+                    let side = if shift < 0 {
+                        Action::Left
+                    } else {
+                        Action::Right
+                    };
+                    actions.extend(std::iter::repeat_n(side, shift.unsigned_abs() as usize));
+                    actions.push(Action::SoftDrop);
+                    actions.push(Action::HardDrop);
+                    best = Some((score, actions));
+                }
+            }
+        }
+        best.map(|(_, actions)| actions)
+            .unwrap_or(vec![Action::HardDrop])
+    }
+
+    /// a game that clear lines with every kind of input, until it ends or `frames` are played
+    fn play(mode: GameMode, seed: u64, frames: u64) -> GameContext {
+        let mut game = GameContext::new(mode, 1, "player".to_owned(), seed);
+        let mut actions: Vec<Action> = Vec::new();
+        while game.frame < frames {
+            // a plan end with a hard drop, the next piece is there once it is done
+            if actions.is_empty() {
+                actions = plan(&game);
+                // undo the last piece and put it back, only zen does something with it
+                if game.stats.pieces % 7 == 3 {
+                    actions.splice(0..0, [Action::Undo, Action::Redo]);
+                }
+                actions.reverse();
+            }
+            let action = match game.frame % 2 {
+                0 => actions.pop(),
+                _ => None,
+            };
+            if let GameEvent::GameOver = step(&mut game, action) {
+                break;
+            }
+        }
+        game
+    }
+
+    #[test]
+    fn text_round_trip_replays_the_same_game() {
+        for mode in GameMode::ALL {
+            let game = play(mode, 42, 10_000);
+            assert!(game.total_lines_cleared > 0, "{}", mode.name());
+            let text = Replay::new(&game).to_text();
+            let replayed = Replay::from_text(&text).expect("own text").simulate();
+            assert_eq!(final_hash(&replayed), final_hash(&game), "{}", mode.name());
+            assert_eq!(replayed.score, game.score);
+            assert_eq!(replayed.total_lines_cleared, game.total_lines_cleared);
+            assert_eq!(replayed.play_time, game.play_time);
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut replay = Replay::new(&play(GameMode::Marathon, 7, 5_000));
+        // big enough for multi byte varints
+        replay.score = u64::MAX;
+        replay.frames = 1 << 40;
+        replay.inputs.push((1 << 40, Action::HardDrop));
+        let decoded = Replay::from_bytes(&replay.to_bytes()).expect("own bytes");
+        assert_eq!(decoded.to_bytes(), replay.to_bytes());
+        assert_eq!(decoded.inputs, replay.inputs);
+        assert_eq!(decoded.score, u64::MAX);
+    }
+
+    #[test]
+    fn long_username_cut_on_a_char_boundary() {
+        let mut replay = Replay::new(&play(GameMode::Zen, 1, 10));
+        replay.username = "é".repeat(200);
+        let decoded = Replay::from_bytes(&replay.to_bytes()).expect("own bytes");
+        assert_eq!(decoded.username, "é".repeat(127));
+    }
+
+    #[test]
+    fn refuse_other_versions_and_rulesets() {
+        let bytes = Replay::new(&play(GameMode::Sprint, 3, 10)).to_bytes();
+        let mut version = bytes.clone();
+        version[MAGIC.len()] = FORMAT_VERSION + 1;
+        assert!(matches!(
+            Replay::from_bytes(&version),
+            Err(ReplayError::UnsupportedFormat(_))
+        ));
+        let mut ruleset = bytes.clone();
+        ruleset[MAGIC.len() + 1..MAGIC.len() + 3].copy_from_slice(&(RULESET + 1).to_le_bytes());
+        assert!(matches!(
+            Replay::from_bytes(&ruleset),
+            Err(ReplayError::IncompatibleRuleset(_))
+        ));
+        assert!(matches!(
+            Replay::from_bytes(b"not a replay"),
+            Err(ReplayError::NotAReplay)
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            Replay::from_bytes(&trailing),
+            Err(ReplayError::Corrupted(_))
+        ));
+        assert!(matches!(
+            Replay::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReplayError::Corrupted(_))
+        ));
+    }
+}
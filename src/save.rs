@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use crate::game::{GameContext, RULESET, pause_menu};
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(serde_json::Error),
+    /// the save was moved to the path, so it is not offered again (None if that failed)
+    Corrupted(serde_json::Error, Option<PathBuf>),
+    IncompatibleRuleset(u16, Option<PathBuf>),
+}
+
+/// where the save went, on its own line after the reason it cannot be resumed
+fn moved(backup: &Option<PathBuf>) -> String {
+    match backup {
+        Some(backup) => format!("\nmoved to {}", backup.display()),
+        None => "\ncould not move it".to_owned(),
+    }
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::Serialize(e) => write!(f, "cannot serialize the game: {}", e),
+            SaveError::Corrupted(e, backup) => {
+                write!(f, "corrupted save: {}{}", e, moved(backup))
+            }
+            SaveError::IncompatibleRuleset(ruleset, backup) => write!(
+                f,
+                "saved with ruleset {}, now {}{}",
+                ruleset,
+                RULESET,
+                moved(backup)
+            ),
+        }
+    }
+}
+
+/// a suspended game, one per user
+#[derive(Serialize, Deserialize)]
+struct SaveFile<G> {
+    ruleset: u16,
+    game: G,
+}
+
+/// the start of a save, the game of another ruleset may not even parse
+#[derive(Deserialize)]
+struct Header {
+    ruleset: u16,
+}
+
+fn save_path(dir: &Path, username: &str) -> PathBuf {
+    dir.join(format!("{}.json", username))
+}
+
+/// move a save that cannot be resumed to `<username>.json.bad`, it would be offered forever
+/// NOTE: kept rather than deleted, like the leaderboard backups
+fn discard(path: &Path) -> Option<PathBuf> {
+    let mut name = path.file_name()?.to_owned();
+    name.push(".bad");
+    let backup = path.with_file_name(name);
+    std::fs::rename(path, &backup).ok()?;
+    Some(backup)
+}
+
+/// delete the suspended game once the resumed game is over
+pub fn remove(dir: &Path, username: &str) -> Result<(), SaveError> {
+    match std::fs::remove_file(save_path(dir, username)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SaveError::Io(e)),
+        _ => Ok(()),
+    }
+}
+
+pub fn exists(dir: &Path, username: &str) -> bool {
+    save_path(dir, username).exists()
+}
+
+/// write the whole game to disk, the game is over for now
+pub fn suspend(dir: &Path, game: &GameContext) -> Result<(), SaveError> {
+    let save_file = SaveFile {
+        ruleset: RULESET,
+        game,
+    };
+    let string = serde_json::to_string(&save_file).map_err(SaveError::Serialize)?;
+    std::fs::create_dir_all(dir).map_err(SaveError::Io)?;
+    // NOTE: a crash mid write would lose the game that was there
+    crate::leaderboard::write_atomic(&save_path(dir, &save_file.game.username), &string)
+        .map_err(SaveError::Io)
+}
+
+/// load the suspended game, the game start paused to give the player time to get back in
+/// NOTE: the save stay until [`remove`] once the game is over, or a crash would lose it
+pub fn resume(dir: &Path, username: &str) -> Result<GameContext, SaveError> {
+    let path = save_path(dir, username);
+    let string = std::fs::read_to_string(&path).map_err(SaveError::Io)?;
+    match serde_json::from_str::<Header>(&string) {
+        Ok(header) if header.ruleset != RULESET => {
+            return Err(SaveError::IncompatibleRuleset(
+                header.ruleset,
+                discard(&path),
+            ));
+        }
+        Ok(_) => {}
+        Err(e) => return Err(SaveError::Corrupted(e, discard(&path))),
+    }
+    let save_file: SaveFile<GameContext> = match serde_json::from_str(&string) {
+        Ok(save_file) => save_file,
+        Err(e) => return Err(SaveError::Corrupted(e, discard(&path))),
+    };
+    let mut game = save_file.game;
+    game.pause = Some(pause_menu());
+    Ok(game)
+}
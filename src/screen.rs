@@ -0,0 +1,287 @@
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::config::{Binding, KeyBindings, KeySpec};
+use crate::game::{GameContext, GameMode, MAX_START_LEVEL};
+use crate::menu::Menu;
+use crate::replay::ReplayViewer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TitleChoice {
+    Resume,
+    Play,
+    Settings,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeChoice {
+    Mode(GameMode),
+    StartLevel,
+    Back,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingsChoice {
+    Binding(Binding),
+    Reset,
+    Save,
+    Back,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResultsChoice {
+    PlayAgain,
+    Menu,
+    Quit,
+}
+
+pub enum Screen {
+    Title(Menu<TitleChoice>),
+    ModeSelect(ModeSelect),
+    Settings(Settings),
+    Game(GameContext),
+    Results(Results),
+    Replay(ReplayViewer),
+}
+
+impl Screen {
+    /// offer to resume when there is a suspended game
+    pub fn title(can_resume: bool) -> Self {
+        let mut items = vec![
+            (TitleChoice::Play, "Play".to_owned()),
+            (TitleChoice::Settings, "Settings".to_owned()),
+            (TitleChoice::Quit, "Quit".to_owned()),
+        ];
+        if can_resume {
+            items.insert(0, (TitleChoice::Resume, "Resume saved game".to_owned()));
+        }
+        Screen::Title(Menu::new("TETRIS", items))
+    }
+}
+
+pub struct ModeSelect {
+    pub menu: Menu<ModeChoice>,
+    pub start_level: u64,
+}
+
+impl ModeSelect {
+    pub fn new(start_level: u64) -> Self {
+        let mut items: Vec<(ModeChoice, String)> = GameMode::ALL
+            .iter()
+            .map(|mode| (ModeChoice::Mode(*mode), mode.name().to_owned()))
+            .collect();
+        items.push((ModeChoice::StartLevel, String::new()));
+        items.push((ModeChoice::Back, "Back".to_owned()));
+        let mut mode_select = ModeSelect {
+            menu: Menu::new("Select mode", items),
+            start_level,
+        };
+        mode_select.set_start_level(start_level);
+        mode_select
+    }
+    fn set_start_level(&mut self, start_level: u64) {
+        self.start_level = start_level.clamp(1, MAX_START_LEVEL);
+        let label = format!("Start level: < {} >", self.start_level);
+        self.menu.set_label(GameMode::ALL.len(), label);
+    }
+    /// left / right change the start level when it is selected
+    pub fn handle_key(&mut self, code: KeyCode) -> Option<ModeChoice> {
+        if self.menu.selected() == ModeChoice::StartLevel {
+            match code {
+                KeyCode::Left | KeyCode::Char('h') => {
+                    self.set_start_level(self.start_level - 1);
+                    return None;
+                }
+                KeyCode::Right | KeyCode::Char('l') => {
+                    self.set_start_level(self.start_level + 1);
+                    return None;
+                }
+                _ => {}
+            }
+        }
+        self.menu.handle_key(code)
+    }
+}
+
+/// controls screen, edit a copy of the bindings until saved
+pub struct Settings {
+    pub menu: Menu<SettingsChoice>,
+    pub keys: KeyBindings,
+    /// waiting for a key, and if it replace the binding or add to it
+    listening: Option<(Binding, bool)>,
+    pub message: String,
+}
+
+impl Settings {
+    pub fn new(keys: KeyBindings) -> Self {
+        let mut items: Vec<(SettingsChoice, String)> = Binding::ALL
+            .iter()
+            .map(|binding| (SettingsChoice::Binding(*binding), String::new()))
+            .collect();
+        items.push((SettingsChoice::Reset, "Reset to defaults".to_owned()));
+        items.push((SettingsChoice::Save, "Save".to_owned()));
+        items.push((SettingsChoice::Back, "Back".to_owned()));
+        let mut settings = Settings {
+            menu: Menu::new("Settings", items),
+            keys,
+            listening: None,
+            message: String::new(),
+        };
+        settings.update_labels();
+        settings
+    }
+    fn update_labels(&mut self) {
+        for (i, binding) in Binding::ALL.iter().enumerate() {
+            let keys: Vec<String> = self
+                .keys
+                .get(*binding)
+                .iter()
+                .map(|key| key.to_string())
+                .collect();
+            let label = format!("{: <10}{: >16}", binding.name(), keys.join(", "));
+            self.menu.set_label(i, label);
+        }
+    }
+    pub fn is_listening(&self) -> bool {
+        self.listening.is_some()
+    }
+    /// enter replace the keys of a binding, `a` add one more
+    /// NOTE: Save and Back are left to the caller
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<SettingsChoice> {
+        if let Some((binding, replace)) = self.listening {
+            self.listening = None;
+            self.bind(binding, KeySpec::from_event(key), replace);
+            return None;
+        }
+        self.message.clear();
+        if let (KeyCode::Char('a'), SettingsChoice::Binding(binding)) =
+            (key.code, self.menu.selected())
+        {
+            self.listening = Some((binding, false));
+            self.message = format!("press a key to add to {}, esc to cancel", binding.name());
+            return None;
+        }
+        match self.menu.handle_key(key.code) {
+            Some(SettingsChoice::Binding(binding)) => {
+                self.listening = Some((binding, true));
+                self.message = format!("press a key for {}, esc to cancel", binding.name());
+                None
+            }
+            Some(SettingsChoice::Reset) => {
+                self.keys = KeyBindings::default();
+                self.update_labels();
+                self.message = "defaults restored, save to keep them".to_owned();
+                None
+            }
+            choice => choice,
+        }
+    }
+    fn bind(&mut self, binding: Binding, key: KeySpec, replace: bool) {
+        if key.code == KeyCode::Esc && key.modifiers.is_empty() {
+            self.message.clear();
+            return;
+        }
+        // only keys the config file can spell
+        if KeySpec::try_from(key.to_string()).is_err() {
+            self.message = format!("{} cannot be bound", key);
+            return;
+        }
+        if let Some(other) = self.keys.conflict(binding, key) {
+            self.message = format!("{} is already bound to {}", key, other.name());
+            return;
+        }
+        let keys = self.keys.get_mut(binding);
+        if replace {
+            keys.clear();
+        }
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+        self.update_labels();
+        self.message = format!("{} bound to {}", key, binding.name());
+    }
+}
+
+impl std::fmt::Display for Settings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.menu)?;
+        writeln!(f, "enter: set key   a: add key")?;
+        write!(f, "\n{}", self.message)
+    }
+}
+
+/// end of game summary, keep the finished game around for play again
+pub struct Results {
+    pub game: GameContext,
+    /// leaderboard rank of this run, None when not ranked (zen, unfinished sprint)
+    pub rank: Option<usize>,
+    pub menu: Menu<ResultsChoice>,
+}
+
+impl Results {
+    pub fn new(game: GameContext, rank: Option<usize>) -> Self {
+        let title = if game.finished() {
+            "FINISHED"
+        } else {
+            "GAME OVER"
+        };
+        Results {
+            game,
+            rank,
+            menu: Menu::new(
+                title,
+                vec![
+                    (ResultsChoice::PlayAgain, "Retry".to_owned()),
+                    (ResultsChoice::Menu, "Menu".to_owned()),
+                    (ResultsChoice::Quit, "Quit".to_owned()),
+                ],
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Results {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let game = &self.game;
+        let stats = &game.stats;
+        writeln!(f, "{}\n", game.mode.name())?;
+        // NOTE: pad everything to the same width, the box is centered
+        let lines = [
+            ("Score", game.score.to_string()),
+            ("Level", game.level.to_string()),
+            ("Lines", game.total_lines_cleared.to_string()),
+            (
+                "Time",
+                format!(
+                    "{:02}:{:02}",
+                    game.play_time.as_secs() / 60,
+                    game.play_time.as_secs() % 60
+                ),
+            ),
+            ("Pieces", stats.pieces.to_string()),
+            ("PPS", format!("{:.2}", stats.pps(game.play_time))),
+            ("", String::new()),
+            ("Singles", stats.singles.to_string()),
+            ("Doubles", stats.doubles.to_string()),
+            ("Triples", stats.triples.to_string()),
+            ("Tetrises", stats.tetrises.to_string()),
+            ("T-spins", stats.t_spins.to_string()),
+            ("Max combo", stats.max_combo.to_string()),
+            ("", String::new()),
+            (
+                "Rank",
+                self.rank
+                    .map(|rank| format!("#{}", rank))
+                    .unwrap_or("-".to_owned()),
+            ),
+        ];
+        for (name, value) in lines {
+            if name.is_empty() {
+                writeln!(f)?;
+                continue;
+            }
+            writeln!(f, "{: <10}{: >10}", name, value)?;
+        }
+        write!(f, "\n{}", self.menu)
+    }
+}
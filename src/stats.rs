@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// what happened during a game, shown on the results screen
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Stats {
+    pub pieces: u64,
+    pub singles: u64,
+    pub doubles: u64,
+    pub triples: u64,
+    pub tetrises: u64,
+    pub t_spins: u64,
+    /// consecutive placements that cleared lines
+    pub streak: u64,
+    pub max_combo: u64,
+    /// the last line clear was a tetris or a t-spin
+    #[serde(default)]
+    pub back_to_back: bool,
+}
+
+impl Stats {
+    pub fn record_placement(&mut self, lines_cleared: u8, t_spin: bool) {
+        self.pieces += 1;
+        if t_spin {
+            self.t_spins += 1;
+        }
+        match lines_cleared {
+            0 => {}
+            1 => self.singles += 1,
+            2 => self.doubles += 1,
+            3 => self.triples += 1,
+            _ => self.tetrises += 1,
+        }
+        if lines_cleared == 0 {
+            self.streak = 0;
+            return;
+        }
+        self.back_to_back = lines_cleared == 4 || t_spin;
+        self.streak += 1;
+        // https://tetris.wiki/Combo the first clear is not a combo yet
+        self.max_combo = self.max_combo.max(self.streak - 1);
+    }
+    /// pieces per second
+    pub fn pps(&self, play_time: Duration) -> f64 {
+        if play_time.is_zero() {
+            return 0.0;
+        }
+        self.pieces as f64 / play_time.as_secs_f64()
+    }
+}
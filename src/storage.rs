@@ -0,0 +1,512 @@
+use std::path::{Path, PathBuf};
+
+use crate::game::GameMode;
+use crate::leaderboard::{Entry, LeaderBoard, LeaderboardError};
+
+/// where the runs are kept, the queries are the ones the game and the cli need
+pub trait Storage {
+    /// the first `top` personal bests with their rank, and the one of `username`
+    /// if it is further down
+    fn top(
+        &self,
+        mode: GameMode,
+        ruleset: u16,
+        top: usize,
+        username: &str,
+    ) -> Result<Vec<(usize, Entry)>, LeaderboardError>;
+    /// position of a run among the bests of the other players, 1 is the best
+    fn rank(&self, entry: &Entry) -> Result<usize, LeaderboardError>;
+    /// every run of a player, oldest first
+    fn history(&self, username: &str) -> Result<Vec<Entry>, LeaderboardError>;
+    /// the modes and rulesets with ranked runs, see `leaderboard::sort_tables`
+    fn tables(&self) -> Result<Vec<(GameMode, u16)>, LeaderboardError>;
+    /// keep some runs at once, the other players may be adding theirs at the same time
+    fn add_all(&mut self, entries: Vec<Entry>) -> Result<(), LeaderboardError>;
+    /// keep a finished run
+    fn add(&mut self, entry: Entry) -> Result<(), LeaderboardError> {
+        self.add_all(vec![entry])
+    }
+}
+
+/// the whole leaderboard in memory, merged into the file at each run
+pub struct JsonFile {
+    path: PathBuf,
+    leaderboard: LeaderBoard,
+}
+
+impl JsonFile {
+    pub fn open(path: &Path) -> Result<Self, LeaderboardError> {
+        Ok(JsonFile {
+            path: path.to_owned(),
+            leaderboard: LeaderBoard::load(path)?,
+        })
+    }
+    /// for when `open` failed, the next save read the file again and keep what it can
+    pub fn empty(path: &Path) -> Self {
+        JsonFile {
+            path: path.to_owned(),
+            leaderboard: LeaderBoard::default(),
+        }
+    }
+}
+
+impl Storage for JsonFile {
+    fn top(
+        &self,
+        mode: GameMode,
+        ruleset: u16,
+        top: usize,
+        username: &str,
+    ) -> Result<Vec<(usize, Entry)>, LeaderboardError> {
+        Ok(self.leaderboard.top(mode, ruleset, top, username))
+    }
+    fn rank(&self, entry: &Entry) -> Result<usize, LeaderboardError> {
+        Ok(self.leaderboard.rank(entry))
+    }
+    fn history(&self, username: &str) -> Result<Vec<Entry>, LeaderboardError> {
+        Ok(self.leaderboard.runs(username))
+    }
+    fn tables(&self) -> Result<Vec<(GameMode, u16)>, LeaderboardError> {
+        Ok(self.leaderboard.tables())
+    }
+    fn add_all(&mut self, entries: Vec<Entry>) -> Result<(), LeaderboardError> {
+        for entry in entries {
+            self.leaderboard.add_entry(entry);
+        }
+        self.leaderboard.save(&self.path)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::Sqlite;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use rusqlite::{Connection, params};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use super::Storage;
+    use crate::game::GameMode;
+    use crate::leaderboard::{Entry, LeaderboardError, Ranking, ranked_lines, sort_tables};
+
+    /// version of the tables, in `PRAGMA user_version`
+    const SCHEMA_VERSION: u16 = 1;
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS runs (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL,
+            mode TEXT NOT NULL,
+            ruleset INTEGER NOT NULL,
+            level INTEGER NOT NULL,
+            score INTEGER NOT NULL,
+            lines INTEGER NOT NULL,
+            time_ns INTEGER NOT NULL,
+            date INTEGER NOT NULL,
+            pieces INTEGER NOT NULL,
+            pps REAL NOT NULL,
+            seed INTEGER NOT NULL,
+            version TEXT NOT NULL,
+            replay TEXT,
+            UNIQUE (username, date, seed)
+        );
+        CREATE INDEX IF NOT EXISTS runs_table ON runs (mode, ruleset, username);
+        CREATE INDEX IF NOT EXISTS runs_user ON runs (username, date);
+    ";
+
+    const COLUMNS: &str = "username, mode, ruleset, level, score, lines, time_ns, date, \
+                           pieces, pps, seed, version, replay";
+
+    /// the personal bests of ?1 mode and ?2 ruleset with at least ?3 lines,
+    /// `{key}` is the result key of the ranking
+    const BESTS: &str = "
+        WITH bests AS (
+            SELECT *, ROW_NUMBER() OVER (PARTITION BY username ORDER BY {key}, date) AS nth
+            FROM runs WHERE mode = ?1 AND ruleset = ?2 AND lines >= ?3
+        )
+    ";
+
+    /// a database next to the replays, sqlite deal with the other players
+    pub struct Sqlite {
+        path: PathBuf,
+        connection: Connection,
+    }
+
+    /// smaller is better, the same order as `Ranking::result`, the rows add the date
+    fn key(ranking: Ranking) -> &'static str {
+        match ranking {
+            Ranking::Score => "-score, time_ns",
+            Ranking::Time => "time_ns, -score",
+        }
+    }
+
+    fn key_values(ranking: Ranking, entry: &Entry) -> [i64; 2] {
+        let (score, time) = (-(entry.score as i64), entry.time.as_nanos() as i64);
+        match ranking {
+            Ranking::Score => [score, time],
+            Ranking::Time => [time, score],
+        }
+    }
+
+    fn mode_from_name(name: &str) -> rusqlite::Result<GameMode> {
+        GameMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == name)
+            .ok_or_else(|| rusqlite::Error::InvalidColumnName(name.to_owned()))
+    }
+
+    /// NOTE: sqlite has no unsigned integers, they are stored as i64
+    fn unsigned(row: &rusqlite::Row, index: usize) -> rusqlite::Result<u64> {
+        Ok(row.get::<_, i64>(index)? as u64)
+    }
+
+    /// a row selected with `COLUMNS`
+    fn entry(row: &rusqlite::Row) -> rusqlite::Result<Entry> {
+        Ok(Entry {
+            username: row.get(0)?,
+            mode: mode_from_name(&row.get::<_, String>(1)?)?,
+            ruleset: row.get(2)?,
+            level: unsigned(row, 3)?,
+            score: unsigned(row, 4)?,
+            lines: unsigned(row, 5)?,
+            time: Duration::from_nanos(unsigned(row, 6)?),
+            date: unsigned(row, 7)?,
+            pieces: unsigned(row, 8)?,
+            pps: row.get(9)?,
+            seed: unsigned(row, 10)?,
+            version: row.get(11)?,
+            replay: row.get(12)?,
+        })
+    }
+
+    impl Sqlite {
+        pub fn open(path: &Path) -> Result<Self, LeaderboardError> {
+            let error = |e| LeaderboardError::Sqlite(path.to_owned(), e);
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| LeaderboardError::Io(dir.to_owned(), e))?;
+            }
+            let created = !path.exists();
+            let connection = Connection::open(path).map_err(error)?;
+            // the others wait for the lock instead of failing
+            connection
+                .busy_timeout(Duration::from_secs(5))
+                .map_err(error)?;
+            let version: u16 = connection
+                .query_row("PRAGMA user_version", [], |row| row.get(0))
+                .map_err(error)?;
+            if version > SCHEMA_VERSION {
+                return Err(LeaderboardError::NewerVersion(path.to_owned(), version));
+            }
+            connection
+                .execute_batch(&format!(
+                    "{} PRAGMA user_version = {};",
+                    SCHEMA, SCHEMA_VERSION
+                ))
+                .map_err(error)?;
+            // written in place, so the other players of a shared dir need to write it too
+            if created && let Some(dir) = path.parent() {
+                let dir_mode = std::fs::metadata(dir)
+                    .map(|metadata| metadata.permissions().mode())
+                    .unwrap_or(0);
+                if dir_mode & 0o020 != 0 {
+                    let mut permissions = std::fs::metadata(path)
+                        .map_err(|e| LeaderboardError::Io(path.to_owned(), e))?
+                        .permissions();
+                    permissions.set_mode(permissions.mode() | 0o060);
+                    std::fs::set_permissions(path, permissions)
+                        .map_err(|e| LeaderboardError::Io(path.to_owned(), e))?;
+                }
+            }
+            Ok(Sqlite {
+                path: path.to_owned(),
+                connection,
+            })
+        }
+        fn error(&self, e: rusqlite::Error) -> LeaderboardError {
+            LeaderboardError::Sqlite(self.path.clone(), e)
+        }
+    }
+
+    impl Storage for Sqlite {
+        fn top(
+            &self,
+            mode: GameMode,
+            ruleset: u16,
+            top: usize,
+            username: &str,
+        ) -> Result<Vec<(usize, Entry)>, LeaderboardError> {
+            let (Some(ranking), Some(lines)) = (Ranking::of(mode), ranked_lines(mode)) else {
+                return Ok(Vec::new());
+            };
+            let key = key(ranking);
+            // NOTE: RANK share the rank of ties, like `LeaderBoard::table`
+            let sql = format!(
+                "{}, ranked AS (
+                    SELECT *, RANK() OVER (ORDER BY {key}) AS rank,
+                        ROW_NUMBER() OVER (ORDER BY {key}, date, username) AS position
+                    FROM bests WHERE nth = 1
+                )
+                SELECT {}, rank FROM ranked WHERE position <= ?4 OR username = ?5
+                ORDER BY position",
+                BESTS.replace("{key}", key),
+                COLUMNS
+            );
+            let mut statement = self.connection.prepare(&sql).map_err(|e| self.error(e))?;
+            let rows = statement
+                .query_map(
+                    params![
+                        mode.name(),
+                        ruleset,
+                        lines as i64,
+                        top.min(i64::MAX as usize) as i64,
+                        username
+                    ],
+                    |row| Ok((unsigned(row, 13)? as usize, entry(row)?)),
+                )
+                .map_err(|e| self.error(e))?;
+            rows.collect::<rusqlite::Result<_>>()
+                .map_err(|e| self.error(e))
+        }
+        fn rank(&self, entry: &Entry) -> Result<usize, LeaderboardError> {
+            let (Some(ranking), Some(lines)) = (Ranking::of(entry.mode), ranked_lines(entry.mode))
+            else {
+                return Ok(1);
+            };
+            let sql = format!(
+                "{} SELECT COUNT(*) FROM bests
+                WHERE nth = 1 AND username != ?4 AND ({}) < (?5, ?6)",
+                BESTS.replace("{key}", key(ranking)),
+                key(ranking)
+            );
+            let [a, b] = key_values(ranking, entry);
+            let better: i64 = self
+                .connection
+                .query_row(
+                    &sql,
+                    params![
+                        entry.mode.name(),
+                        entry.ruleset,
+                        lines as i64,
+                        entry.username,
+                        a,
+                        b
+                    ],
+                    |row| row.get(0),
+                )
+                .map_err(|e| self.error(e))?;
+            Ok(better as usize + 1)
+        }
+        fn history(&self, username: &str) -> Result<Vec<Entry>, LeaderboardError> {
+            let sql = format!(
+                "SELECT {} FROM runs WHERE username = ?1 ORDER BY date, id",
+                COLUMNS
+            );
+            let mut statement = self.connection.prepare(&sql).map_err(|e| self.error(e))?;
+            let rows = statement
+                .query_map([username], entry)
+                .map_err(|e| self.error(e))?;
+            rows.collect::<rusqlite::Result<_>>()
+                .map_err(|e| self.error(e))
+        }
+        fn tables(&self) -> Result<Vec<(GameMode, u16)>, LeaderboardError> {
+            let mut statement = self
+                .connection
+                .prepare("SELECT mode, ruleset, MAX(lines) FROM runs GROUP BY mode, ruleset")
+                .map_err(|e| self.error(e))?;
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((
+                        mode_from_name(&row.get::<_, String>(0)?)?,
+                        row.get::<_, u16>(1)?,
+                        unsigned(row, 2)?,
+                    ))
+                })
+                .map_err(|e| self.error(e))?;
+            let mut keys = Vec::new();
+            for row in rows {
+                let (mode, ruleset, lines) = row.map_err(|e| self.error(e))?;
+                if ranked_lines(mode).is_some_and(|needed| lines >= needed) {
+                    keys.push((mode, ruleset));
+                }
+            }
+            sort_tables(&mut keys);
+            Ok(keys)
+        }
+        fn add_all(&mut self, entries: Vec<Entry>) -> Result<(), LeaderboardError> {
+            let sql = format!(
+                "INSERT OR IGNORE INTO runs ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                COLUMNS
+            );
+            let error = |e| LeaderboardError::Sqlite(self.path.clone(), e);
+            // NOTE: one transaction, the others see all of them or none
+            let transaction = self.connection.transaction().map_err(error)?;
+            let mut statement = transaction.prepare(&sql).map_err(error)?;
+            for entry in entries {
+                statement
+                    .execute(params![
+                        entry.username,
+                        entry.mode.name(),
+                        entry.ruleset,
+                        entry.level as i64,
+                        entry.score as i64,
+                        entry.lines as i64,
+                        entry.time.as_nanos() as i64,
+                        entry.date as i64,
+                        entry.pieces as i64,
+                        entry.pps,
+                        entry.seed as i64,
+                        entry.version,
+                        entry.replay
+                    ])
+                    .map_err(error)?;
+            }
+            drop(statement);
+            transaction.commit().map_err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leaderboard::tests::{run, temp_dir};
+
+    fn players(storage: &dyn Storage) -> Vec<String> {
+        let mut players: Vec<String> = storage
+            .top(GameMode::Marathon, crate::game::RULESET, usize::MAX, "")
+            .unwrap()
+            .into_iter()
+            .map(|(_, entry)| entry.username)
+            .collect();
+        players.sort();
+        players
+    }
+
+    #[test]
+    fn two_json_files_keep_the_runs_of_each_other() {
+        let path = temp_dir("two-json").join("scores");
+        let mut first = JsonFile::open(&path).unwrap();
+        let mut second = JsonFile::open(&path).unwrap();
+        first.add(run("alice", 100, 1)).unwrap();
+        second.add(run("bob", 200, 2)).unwrap();
+        // the second one read the first run back when it saved, not the first one yet
+        assert_eq!(players(&second), ["alice", "bob"]);
+        assert_eq!(players(&first), ["alice"]);
+        first.add(run("carol", 300, 3)).unwrap();
+        assert_eq!(players(&first), ["alice", "bob", "carol"]);
+        assert_eq!(
+            players(&JsonFile::open(&path).unwrap()),
+            ["alice", "bob", "carol"]
+        );
+    }
+
+    /// what the two backends must agree on, `Entry` has floats
+    #[cfg(feature = "sqlite")]
+    fn fields(entry: &Entry) -> (String, &'static str, u16, u64, u64, u64) {
+        let time = entry.time.as_millis() as u64;
+        let name = entry.mode.name();
+        (
+            entry.username.clone(),
+            name,
+            entry.ruleset,
+            entry.score,
+            time,
+            entry.date,
+        )
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn json_and_sqlite_rank_the_same() {
+        use crate::game::{RULESET, SPRINT_LINES};
+        use std::time::Duration;
+
+        let with = |mut entry: Entry, mode: GameMode, lines: u64, secs: u64| {
+            entry.mode = mode;
+            entry.lines = lines;
+            entry.time = Duration::from_secs(secs);
+            entry
+        };
+        let marathon = |username, score, secs, date| {
+            with(run(username, score, date), GameMode::Marathon, 10, secs)
+        };
+        let sprint = |username, score, lines, secs, date| {
+            with(run(username, score, date), GameMode::Sprint, lines, secs)
+        };
+        let mut old = marathon("alice", 900, 60, 20);
+        old.ruleset = RULESET - 1;
+        let runs = vec![
+            marathon("alice", 100, 60, 1),
+            marathon("dave", 50, 60, 2),
+            // a tie with alice below, bob got there first
+            marathon("bob", 300, 90, 3),
+            // the same score, faster
+            marathon("carol", 300, 80, 4),
+            marathon("alice", 300, 90, 5),
+            marathon("erin", 200, 60, 6),
+            sprint("alice", 1000, SPRINT_LINES, 60, 7),
+            sprint("bob", 1000, SPRINT_LINES, 60, 8),
+            // the same time, more points
+            sprint("carol", 2000, SPRINT_LINES, 60, 9),
+            // not done, not ranked
+            sprint("dave", 3000, SPRINT_LINES - 1, 50, 10),
+            sprint("erin", 500, SPRINT_LINES, 70, 11),
+            with(run("frank", 5000, 12), GameMode::Zen, 100, 600),
+            old,
+        ];
+
+        let dir = temp_dir("parity");
+        let mut json = JsonFile::open(&dir.join("scores")).unwrap();
+        let mut sqlite = Sqlite::open(&dir.join("scores.db")).unwrap();
+        json.add_all(runs.clone()).unwrap();
+        sqlite.add_all(runs.clone()).unwrap();
+        let backends: [&dyn Storage; 2] = [&json, &sqlite];
+
+        let tables = json.tables().unwrap();
+        assert_eq!(tables, sqlite.tables().unwrap());
+        assert_eq!(tables.len(), 3);
+        for (mode, ruleset) in tables {
+            for (top, username) in [(usize::MAX, ""), (2, "erin"), (0, "alice")] {
+                let [a, b] = backends.map(|storage| {
+                    let rows = storage.top(mode, ruleset, top, username).unwrap();
+                    rows.iter()
+                        .map(|(rank, entry)| (*rank, fields(entry)))
+                        .collect::<Vec<_>>()
+                });
+                assert_eq!(a, b, "{} {} top {}", mode.name(), ruleset, top);
+            }
+        }
+        let marathon_ranks: Vec<usize> = json
+            .top(GameMode::Marathon, RULESET, usize::MAX, "")
+            .unwrap()
+            .iter()
+            .map(|(rank, _)| *rank)
+            .collect();
+        assert_eq!(marathon_ranks, [1, 2, 2, 4, 5]);
+
+        let probes = [
+            marathon("zoe", 300, 90, 30),
+            marathon("zoe", 300, 85, 31),
+            marathon("zoe", 10, 60, 32),
+            marathon("bob", 1000, 60, 33),
+            sprint("zoe", 1000, SPRINT_LINES, 60, 34),
+            sprint("zoe", 100, SPRINT_LINES, 65, 35),
+            sprint("carol", 100, SPRINT_LINES, 100, 36),
+        ];
+        for probe in probes.iter() {
+            let [a, b] = backends.map(|storage| storage.rank(probe).unwrap());
+            assert_eq!(a, b, "{:?}", fields(probe));
+        }
+
+        for username in ["alice", "bob", "dave", "frank", "nobody"] {
+            let [a, b] = backends.map(|storage| {
+                let runs = storage.history(username).unwrap();
+                runs.iter().map(fields).collect::<Vec<_>>()
+            });
+            assert_eq!(a, b, "{}", username);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
 
 use crate::grid::*;
 use crate::vec2::Vec2;
@@ -9,16 +9,42 @@ pub enum Collision {
     OutOfBound,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PieceKind {
+    I,
+    O,
+    T,
+    S,
+    Z,
+    J,
+    L,
+}
+
+impl PieceKind {
+    pub fn letter(&self) -> char {
+        match self {
+            PieceKind::I => 'I',
+            PieceKind::O => 'O',
+            PieceKind::T => 'T',
+            PieceKind::S => 'S',
+            PieceKind::Z => 'Z',
+            PieceKind::J => 'J',
+            PieceKind::L => 'L',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tetromino {
+    pub kind: PieceKind,
     pub blocks: Vec<Vec2>,
     pub does_rotate: bool,
     pub pos: Vec2,
-    pub color: Color,
 }
 impl Default for Tetromino {
     fn default() -> Self {
         Tetromino {
+            kind: PieceKind::I,
             blocks: vec![
                 Vec2::new(0, 0),
                 Vec2::new(-1, -1),
@@ -31,7 +57,6 @@ impl Default for Tetromino {
                 x: GRID_WIDTH as i8 / 2,
                 y: 1,
             },
-            color: Color::Red,
         }
     }
 }
@@ -50,11 +75,11 @@ impl Tetromino {
             if grid[grid_y as usize][grid_x as usize].is_some() {
                 return Err(Collision::Occupied);
             }
-            grid[grid_y as usize][grid_x as usize] = Some(self.color);
+            grid[grid_y as usize][grid_x as usize] = Some(self.kind);
             Ok(())
         })
     }
-    pub fn rotate(&mut self) -> () {
+    pub fn rotate(&mut self) {
         if !self.does_rotate {
             return;
         }
@@ -75,13 +100,33 @@ impl Tetromino {
         }
         false
     }
-    pub fn try_move_down(&mut self, grid: &Grid) -> Result<(), ()> {
+    /// 3 corner rule, the caller check that the last move was a rotation
+    pub fn is_t_spin(&self, grid: &Grid) -> bool {
+        if self.kind != PieceKind::T {
+            return false;
+        }
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .iter()
+            .filter(|(x, y)| {
+                let x = self.pos.x + x;
+                let y = self.pos.y + y;
+                x < 0
+                    || y < 0
+                    || x as usize >= GRID_WIDTH
+                    || y as usize >= GRID_HEIGHT
+                    || grid[y as usize][x as usize].is_some()
+            })
+            .count()
+            >= 3
+    }
+    /// false if it cannot go down, it stay in place then
+    pub fn try_move_down(&mut self, grid: &Grid) -> bool {
         self.pos.y += 1;
         if self.collide(grid) {
             self.pos.y -= 1;
-            Err(())
+            false
         } else {
-            Ok(())
+            true
         }
     }
 }
@@ -0,0 +1,376 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::border;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::color::ColorSupport;
+use crate::config::{ConfigError, DisplayConfig};
+use crate::tetromino::PieceKind;
+
+/// the themes that dont need a file
+pub const PRESETS: [&str; 4] = ["default", "guideline", "nes", "mono"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BorderStyle {
+    Plain,
+    Rounded,
+    Double,
+    Thick,
+}
+
+impl BorderStyle {
+    pub fn set(&self) -> border::Set {
+        match self {
+            BorderStyle::Plain => border::PLAIN,
+            BorderStyle::Rounded => border::ROUNDED,
+            BorderStyle::Double => border::DOUBLE,
+            BorderStyle::Thick => border::THICK,
+        }
+    }
+}
+
+/// piece colors that stay apart with a color vision deficiency
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    /// the colors of the theme
+    #[default]
+    Theme,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl Palette {
+    fn colors(&self) -> Option<PieceColors> {
+        match self {
+            Palette::Theme => None,
+            // https://jfly.uni-koeln.de/color/ (Okabe and Ito)
+            Palette::Deuteranopia => Some(PieceColors {
+                i: Color::Rgb(86, 180, 233),
+                o: Color::Rgb(240, 228, 66),
+                t: Color::Rgb(204, 121, 167),
+                s: Color::Rgb(0, 158, 115),
+                z: Color::Rgb(213, 94, 0),
+                j: Color::Rgb(0, 114, 178),
+                l: Color::Rgb(230, 159, 0),
+            }),
+            // same but red look dark, so no vermillion
+            Palette::Protanopia => Some(PieceColors {
+                i: Color::Rgb(86, 180, 233),
+                o: Color::Rgb(240, 228, 66),
+                t: Color::Rgb(204, 121, 167),
+                s: Color::Rgb(0, 158, 115),
+                z: Color::Rgb(240, 240, 240),
+                j: Color::Rgb(0, 114, 178),
+                l: Color::Rgb(230, 159, 0),
+            }),
+            // blue and yellow are the problem, lean on red, teal and lightness
+            Palette::Tritanopia => Some(PieceColors {
+                i: Color::Rgb(0, 170, 170),
+                o: Color::Rgb(240, 240, 240),
+                t: Color::Rgb(235, 130, 175),
+                s: Color::Rgb(0, 100, 100),
+                z: Color::Rgb(220, 40, 30),
+                j: Color::Rgb(110, 110, 110),
+                l: Color::Rgb(140, 0, 0),
+            }),
+        }
+    }
+}
+
+/// something other than the color to tell the pieces apart
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellLabels {
+    #[default]
+    None,
+    /// the piece letter on a colored background
+    Letters,
+    /// a different glyph per piece instead of `cell`
+    Patterns,
+}
+
+/// one color per tetromino
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PieceColors {
+    pub i: Color,
+    pub o: Color,
+    pub t: Color,
+    pub s: Color,
+    pub z: Color,
+    pub j: Color,
+    pub l: Color,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GhostStyle {
+    /// drawn like `cell`
+    pub glyph: String,
+    /// None to use the piece color
+    pub color: Option<Color>,
+}
+
+/// how the game look, the presets or a toml file in the themes dir
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub pieces: PieceColors,
+    /// filled cell, repeated to the cell width (a cell is two columns wide)
+    pub cell: String,
+    pub ghost: GhostStyle,
+    /// drawn in the top left of empty cells
+    pub empty: String,
+    pub border: BorderStyle,
+    pub border_color: Color,
+    pub text_color: Color,
+    pub garbage_color: Color,
+    /// flashing rows, like line clears
+    pub highlight_color: Color,
+    pub labels: CellLabels,
+    /// bold cells and text
+    pub bold: bool,
+}
+
+impl Default for GhostStyle {
+    fn default() -> Self {
+        GhostStyle {
+            glyph: "░░".to_owned(),
+            color: None,
+        }
+    }
+}
+
+impl Default for Theme {
+    /// the original look
+    fn default() -> Self {
+        Theme {
+            pieces: PieceColors {
+                i: Color::Red,
+                o: Color::Blue,
+                t: Color::Yellow,
+                s: Color::Cyan,
+                z: Color::Green,
+                j: Color::White,
+                l: Color::Magenta,
+            },
+            cell: "██".to_owned(),
+            ghost: GhostStyle::default(),
+            empty: ".".to_owned(),
+            border: BorderStyle::Rounded,
+            border_color: Color::DarkGray,
+            text_color: Color::White,
+            garbage_color: Color::Gray,
+            highlight_color: Color::White,
+            labels: CellLabels::None,
+            bold: false,
+        }
+    }
+}
+
+impl Theme {
+    pub fn preset(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            // https://tetris.wiki/Tetris_Guideline
+            "guideline" => Some(Theme {
+                pieces: PieceColors {
+                    i: Color::Rgb(0, 255, 255),
+                    o: Color::Rgb(255, 255, 0),
+                    t: Color::Rgb(160, 0, 240),
+                    s: Color::Rgb(0, 255, 0),
+                    z: Color::Rgb(255, 0, 0),
+                    j: Color::Rgb(0, 0, 255),
+                    l: Color::Rgb(255, 165, 0),
+                },
+                ..Theme::default()
+            }),
+            // level 0 of the NES version, 3 colors and bracket blocks
+            "nes" => Some(Theme {
+                pieces: PieceColors {
+                    i: Color::Rgb(240, 240, 240),
+                    o: Color::Rgb(240, 240, 240),
+                    t: Color::Rgb(240, 240, 240),
+                    s: Color::Rgb(0, 88, 248),
+                    z: Color::Rgb(60, 188, 252),
+                    j: Color::Rgb(0, 88, 248),
+                    l: Color::Rgb(60, 188, 252),
+                },
+                cell: "[]".to_owned(),
+                ghost: GhostStyle {
+                    glyph: "::".to_owned(),
+                    color: Some(Color::DarkGray),
+                },
+                empty: " ".to_owned(),
+                border: BorderStyle::Double,
+                border_color: Color::Gray,
+                text_color: Color::White,
+                garbage_color: Color::Rgb(124, 124, 124),
+                highlight_color: Color::White,
+                labels: CellLabels::None,
+                bold: false,
+            }),
+            "mono" => Some(Theme {
+                pieces: PieceColors {
+                    i: Color::White,
+                    o: Color::White,
+                    t: Color::White,
+                    s: Color::White,
+                    z: Color::White,
+                    j: Color::White,
+                    l: Color::White,
+                },
+                ghost: GhostStyle {
+                    glyph: "░░".to_owned(),
+                    color: Some(Color::Gray),
+                },
+                border: BorderStyle::Plain,
+                border_color: Color::Gray,
+                ..Theme::default()
+            }),
+            _ => None,
+        }
+    }
+    /// `<themes dir>/<name>.toml` if it exist, a preset otherwise
+    pub fn load(name: &str, themes_dir: Option<&Path>) -> Result<Theme, ConfigError> {
+        if let Some(dir) = themes_dir {
+            let path = dir.join(format!("{}.toml", name));
+            match std::fs::read_to_string(&path) {
+                Ok(string) => {
+                    let theme: Theme =
+                        toml::from_str(&string).map_err(|e| ConfigError::Parse(path.clone(), e))?;
+                    theme
+                        .validate()
+                        .map_err(|message| ConfigError::Invalid(path, message))?;
+                    return Ok(theme);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(ConfigError::Io(path, e)),
+            }
+        }
+        Theme::preset(name).ok_or_else(|| {
+            ConfigError::Invalid(
+                themes_dir
+                    .unwrap_or(Path::new(""))
+                    .join(format!("{}.toml", name)),
+                format!(
+                    "unknown theme \"{}\", expected a file or one of {}",
+                    name,
+                    PRESETS.join(", ")
+                ),
+            )
+        })
+    }
+    /// the accessibility settings win over the theme
+    pub fn adjust(&mut self, display: &DisplayConfig) {
+        if let Some(colors) = display.palette.colors() {
+            self.pieces = colors;
+        }
+        if display.labels != CellLabels::None {
+            self.labels = display.labels;
+        }
+        if display.high_contrast {
+            self.border_color = Color::White;
+            self.text_color = Color::White;
+            self.ghost.color = Some(Color::White);
+            self.bold = true;
+        }
+    }
+    /// fit the colors to the terminal
+    pub fn downmap(&mut self, support: ColorSupport) {
+        let pieces = &mut self.pieces;
+        for color in [
+            &mut pieces.i,
+            &mut pieces.o,
+            &mut pieces.t,
+            &mut pieces.s,
+            &mut pieces.z,
+            &mut pieces.j,
+            &mut pieces.l,
+            &mut self.border_color,
+            &mut self.text_color,
+            &mut self.garbage_color,
+            &mut self.highlight_color,
+        ] {
+            *color = support.downmap(*color);
+        }
+        self.ghost.color = self.ghost.color.map(|color| support.downmap(color));
+        // without colors the pieces need something else to tell them apart
+        if support == ColorSupport::None && self.labels == CellLabels::None {
+            self.labels = CellLabels::Patterns;
+        }
+    }
+    fn validate(&self) -> Result<(), String> {
+        // NOTE: width in chars, close enough for the block and box drawing glyphs
+        for (name, glyph) in [("cell", &self.cell), ("ghost.glyph", &self.ghost.glyph)] {
+            if !(1..=2).contains(&glyph.chars().count()) {
+                return Err(format!("{}: must be 1 or 2 characters", name));
+            }
+        }
+        if self.empty.chars().count() > 2 {
+            return Err("empty: must be at most 2 characters".to_owned());
+        }
+        Ok(())
+    }
+    pub fn color(&self, kind: PieceKind) -> Color {
+        match kind {
+            PieceKind::I => self.pieces.i,
+            PieceKind::O => self.pieces.o,
+            PieceKind::T => self.pieces.t,
+            PieceKind::S => self.pieces.s,
+            PieceKind::Z => self.pieces.z,
+            PieceKind::J => self.pieces.j,
+            PieceKind::L => self.pieces.l,
+        }
+    }
+    pub fn style(&self, color: Color) -> Style {
+        let style = Style::default().fg(color);
+        if self.bold {
+            style.add_modifier(Modifier::BOLD)
+        } else {
+            style
+        }
+    }
+    /// one row of a filled cell, letters only go on the first row
+    pub fn cell(&self, kind: PieceKind, width: u16, first_row: bool) -> (String, Style) {
+        let color = self.color(kind);
+        match self.labels {
+            CellLabels::None => (Theme::fill(&self.cell, width), self.style(color)),
+            CellLabels::Letters => {
+                let mut text = " ".repeat(width as usize);
+                if first_row && width > 0 {
+                    let middle = (width as usize - 1) / 2;
+                    text.replace_range(middle..middle + 1, &kind.letter().to_string());
+                }
+                let style = match color {
+                    // no colors, reverse video still stand out
+                    Color::Reset => self.style(color).add_modifier(Modifier::REVERSED),
+                    _ => self.style(Color::Black).bg(color),
+                };
+                (text, style)
+            }
+            CellLabels::Patterns => {
+                let pattern = match kind {
+                    PieceKind::I => "██",
+                    PieceKind::O => "▓▓",
+                    PieceKind::T => "▒▒",
+                    PieceKind::S => "//",
+                    PieceKind::Z => "\\\\",
+                    PieceKind::J => "<>",
+                    PieceKind::L => "##",
+                };
+                (Theme::fill(pattern, width), self.style(color))
+            }
+        }
+    }
+    /// `glyph` repeated to fill `width` columns
+    pub fn fill(glyph: &str, width: u16) -> String {
+        let glyph_width = glyph.chars().count().max(1);
+        let mut string = glyph.repeat(width as usize / glyph_width);
+        string.extend(glyph.chars().take(width as usize % glyph_width));
+        string
+    }
+}
@@ -1,6 +1,7 @@
+use serde::{Deserialize, Serialize};
 use std::ops::Add;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: i8,
     pub y: i8,
@@ -10,11 +11,6 @@ impl Vec2 {
         Vec2 { x, y }
     }
 }
-impl Default for Vec2 {
-    fn default() -> Self {
-        Vec2 { x: 0, y: 0 }
-    }
-}
 impl Add for Vec2 {
     type Output = Self;
     fn add(self, rhs: Self) -> Self::Output {
@@ -0,0 +1,190 @@
+use crossterm::event::{Event, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Paragraph, StatefulWidget, Widget},
+};
+use std::collections::VecDeque;
+
+use crate::animation::Animations;
+use crate::board::{Board, BoardMode};
+use crate::config::Config;
+use crate::game::{GameContext, GameEvent, update};
+use crate::input::{Input, queue_event};
+use crate::render::{panel, preview_lines};
+use crate::theme::Theme;
+
+/// next pieces column, 4 cells of 2 columns and the borders
+const PREVIEW_WIDTH: u16 = 10;
+
+/// a playfield over any `GameContext`, live, replayed or spectated
+///
+/// ```ignore
+/// let widget = TetrisWidget::new(&theme).preview(3).animations(&tetris.animations);
+/// frame.render_stateful_widget(widget, area, &mut tetris.game);
+/// ```
+pub struct TetrisWidget<'a> {
+    theme: &'a Theme,
+    ghost: bool,
+    preview: usize,
+    block: Option<Block<'a>>,
+    animations: Option<&'a Animations>,
+}
+
+impl<'a> TetrisWidget<'a> {
+    pub fn new(theme: &'a Theme) -> Self {
+        TetrisWidget {
+            theme,
+            ghost: true,
+            preview: 0,
+            block: None,
+            animations: None,
+        }
+    }
+    pub fn ghost(mut self, ghost: bool) -> Self {
+        self.ghost = ghost;
+        self
+    }
+    /// number of next pieces shown on the right, when there is room
+    pub fn preview(mut self, preview: usize) -> Self {
+        self.preview = preview;
+        self
+    }
+    pub fn animations(mut self, animations: &'a Animations) -> Self {
+        self.animations = Some(animations);
+        self
+    }
+    /// replace the " Playfield " panel
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+}
+
+impl StatefulWidget for TetrisWidget<'_> {
+    type State = GameContext;
+
+    fn render(self, area: Rect, buf: &mut Buffer, game: &mut GameContext) {
+        let theme = self.theme;
+        let mut playfield_area = area;
+        if self.preview > 0 && area.width >= PREVIEW_WIDTH * 2 {
+            let [playfield, preview] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Length(PREVIEW_WIDTH)])
+                    .areas(area);
+            let mut lines = Vec::new();
+            // the next ones are right before the current one
+            for tetromino in game.bag.iter().rev().skip(1).take(self.preview) {
+                lines.extend(preview_lines(tetromino, theme));
+                lines.push(Line::from(""));
+            }
+            Paragraph::new(lines)
+                .block(panel(theme, " Next "))
+                .render(preview, buf);
+            playfield_area = playfield;
+        }
+
+        // shrink to the board, centered
+        let block = self.block.unwrap_or_else(|| panel(theme, " Playfield "));
+        let inner = block.inner(playfield_area);
+        if let Some(mode) = BoardMode::fit(inner.width, inner.height) {
+            let (width, height) = mode.size();
+            let extra = (
+                playfield_area.width - inner.width,
+                playfield_area.height - inner.height,
+            );
+            playfield_area = Rect {
+                x: playfield_area.x + (inner.width - width) / 2,
+                width: width + extra.0,
+                height: height + extra.1,
+                ..playfield_area
+            };
+        }
+        let inner = block.inner(playfield_area);
+        block.render(playfield_area, buf);
+
+        // hide the board while paused so nobody can plan mid-game
+        if let Some(pause_menu) = &game.pause {
+            let text = format!("{}\n\n{}", pause_menu.title, pause_menu);
+            // NOTE: too narrow, the host can show the menu elsewhere
+            let fits = text.lines().all(|line| line.len() <= inner.width as usize);
+            let text = if fits { text } else { "PAUSED".to_owned() };
+            let height = text.lines().count() as u16;
+            let rect = Rect {
+                y: inner.y + inner.height.saturating_sub(height) / 2,
+                height: height.min(inner.height),
+                ..inner
+            };
+            Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(theme.text_color))
+                .render(rect, buf);
+            return;
+        }
+        let mut board = Board::new(&game.grid, theme)
+            .active(game.bag.last().expect("bag empty in rendering"))
+            .ghost(self.ghost);
+        if let Some(animations) = self.animations {
+            board = board
+                .highlights(animations.highlights())
+                .flash(animations.flash());
+        }
+        board.render(inner, buf);
+
+        // a third of the way down, over the board
+        if let Some((text, fading)) = self.animations.and_then(Animations::popup) {
+            let mut style = theme.style(theme.text_color).add_modifier(Modifier::BOLD);
+            if fading {
+                style = style.add_modifier(Modifier::DIM);
+            }
+            let rect = Rect {
+                y: inner.y + inner.height / 3,
+                height: 1.min(inner.height),
+                ..inner
+            };
+            Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .style(style)
+                .render(rect, buf);
+        }
+    }
+}
+
+/// a game for a host app to drive, it feed the events and call `tick`
+/// `TARGET_FPS` times a second, then render `game` with a `TetrisWidget`
+pub struct Tetris {
+    pub game: GameContext,
+    pub input: Input,
+    pub animations: Animations,
+    /// the engine take at most one event per frame
+    events: VecDeque<Event>,
+}
+
+impl Tetris {
+    /// `release_events` if the host enabled crossterm keyboard enhancement
+    pub fn new(game: GameContext, config: &Config, release_events: bool) -> Self {
+        Tetris {
+            game,
+            input: Input::new(config, release_events),
+            animations: Animations::new(config.display.reduced_motion),
+            events: VecDeque::new(),
+        }
+    }
+    pub fn handle_event(&mut self, event: Event) {
+        queue_event(&mut self.events, event);
+    }
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        self.handle_event(Event::Key(key));
+    }
+    /// one frame of the game
+    pub fn tick(&mut self) -> GameEvent {
+        let event = self.events.pop_front();
+        let game_event = update(&mut self.game, &mut self.input, event);
+        for engine_event in self.game.events.drain(..) {
+            self.animations.push(engine_event);
+        }
+        self.animations.tick();
+        game_event
+    }
+}